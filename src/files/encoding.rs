@@ -0,0 +1,111 @@
+use std::borrow::Cow;
+
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+
+pub struct Decoded<'a> {
+    pub text: Cow<'a, str>, // borrows the input for utf-8, no copy of huge files
+    pub encoding: &'static Encoding,
+    pub bom: bool,
+    pub error: Option<String>, // set when the bytes don't survive decode + encode
+    pub lossy: bool, // invalid bytes were decoded as U+FFFD
+}
+
+/** BOM first, then a NUL byte heuristic for utf-16, then valid utf-8, else latin-1 (windows-1252) */
+pub fn detect(bytes: &[u8]) -> (&'static Encoding, usize) {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(bytes) {
+        return (encoding, bom_len);
+    }
+
+    // ascii heavy utf-16 has every other byte zeroed
+    let sample = &bytes[..bytes.len().min(4096)];
+    if sample.len() >= 2 && sample.len() % 2 == 0 {
+        let even_nuls = sample.iter().step_by(2).filter(|b| **b == 0).count();
+        let odd_nuls = sample.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+        let half = sample.len() / 2;
+        if odd_nuls * 10 > half * 3 && even_nuls * 10 < half {
+            return (UTF_16LE, 0);
+        }
+        if even_nuls * 10 > half * 3 && odd_nuls * 10 < half {
+            return (UTF_16BE, 0);
+        }
+    }
+    if std::str::from_utf8(bytes).is_ok() {
+        return (UTF_8, 0);
+    }
+    (WINDOWS_1252, 0)
+}
+
+pub fn decode(bytes: &[u8]) -> Decoded<'_> {
+    let (encoding, bom_len) = detect(bytes);
+    decode_as(bytes, encoding, bom_len)
+}
+
+/** `bytes` as `encoding` whatever detection says, a BOM for it is skipped */
+pub fn decode_with(bytes: &[u8], encoding: &'static Encoding) -> Decoded<'_> {
+    let bom_len = match Encoding::for_bom(bytes) {
+        Some((bom, len)) if bom == encoding => len,
+        _ => 0,
+    };
+    decode_as(bytes, encoding, bom_len)
+}
+
+fn decode_as(bytes: &[u8], encoding: &'static Encoding, bom_len: usize) -> Decoded<'_> {
+    let body = &bytes[bom_len..];
+
+    let (mut error, mut lossy) = (None, false);
+    let text = match encoding.decode_without_bom_handling_and_without_replacement(body) {
+        Some(text) => text,
+        None => {
+            lossy = true;
+            error = Some(format!("invalid {} data", encoding.name()));
+            encoding.decode_without_bom_handling(body).0
+        }
+    };
+
+    // anything that wouldn't save back byte for byte can't be edited safely,
+    // valid utf-8 always does so skip re-encoding big files
+    if error.is_none()
+        && encoding != UTF_8
+        && encode(&text, encoding, bom_len > 0).ok().as_deref() != Some(bytes)
+    {
+        error = Some(format!("{} does not round-trip", encoding.name()));
+    }
+
+    Decoded {
+        text,
+        encoding,
+        bom: bom_len > 0,
+        lossy,
+        error,
+    }
+}
+
+pub fn encode(text: &str, encoding: &'static Encoding, bom: bool) -> Result<Vec<u8>, String> {
+    // encoding_rs only decodes utf-16, so encode it by hand
+    if encoding == UTF_16LE || encoding == UTF_16BE {
+        let mut bytes = Vec::with_capacity(text.len() * 2 + 2);
+        if bom {
+            bytes.extend(if encoding == UTF_16LE { [0xff, 0xfe] } else { [0xfe, 0xff] });
+        }
+        for unit in text.encode_utf16() {
+            bytes.extend(if encoding == UTF_16LE { unit.to_le_bytes() } else { unit.to_be_bytes() });
+        }
+        return Ok(bytes);
+    }
+
+    let (encoded, used, unmappable) = encoding.encode(text);
+    if unmappable || used != encoding {
+        return Err(format!("{} cannot represent this text", encoding.name()));
+    }
+    let mut bytes = vec![];
+    if bom && encoding == UTF_8 {
+        bytes.extend([0xef, 0xbb, 0xbf]);
+    }
+    bytes.extend_from_slice(&encoded);
+    Ok(bytes)
+}
+
+/** takes labels like utf-8, latin1, utf-16le */
+pub fn from_label(label: &str) -> Option<&'static Encoding> {
+    Encoding::for_label(label.as_bytes())
+}
@@ -1,38 +1,240 @@
-use std::{fs::File, io::{Read, Write}};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
+use encoding_rs::Encoding;
 use gpui::SharedString;
+use memmap2::Mmap;
 
+use super::{
+    encoding::{decode, decode_with, encode, Decoded},
+    format::{split_lines, FileFormat, LineEnding},
+};
 
-/** returns vec of shared string */
-pub fn load_file(path: String) -> Vec<SharedString>{
-    let mut file = File::open(path.clone());
-    if file.is_err() {
-        println!("{}", path.clone() + " NOT FOUND, creataing");
-        file =  File::create(path.clone());
+/** why a buffer was opened read only, only some of them another encoding can fix */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadOnly {
+    RoundTrip, // decoded fine but wouldn't save back byte for byte
+    Lossy, // invalid bytes became U+FFFD, saving would lose them
+    Unreadable,
+    View, // not a file, like the disk diff
+}
+
+pub struct LoadedFile {
+    pub lines: Vec<SharedString>,
+    pub format: FileFormat,
+    pub error: Option<String>, // buffer must stay read only when set
+    pub read_only: Option<ReadOnly>, // set along with error
+}
+
+impl LoadedFile {
+    /** what a buffer holds while the real file loads in the background */
+    pub fn empty() -> Self {
+        LoadedFile {
+            lines: vec!["".into()],
+            format: FileFormat::default(),
+            error: None,
+            read_only: None,
+        }
     }
+}
+
+/** lines indexed so far out of the total, polled by the ui while loading */
+#[derive(Default)]
+pub struct LoadProgress {
+    pub done: AtomicUsize,
+    pub total: AtomicUsize,
+}
+
+impl LoadProgress {
+    pub fn percent(&self) -> usize {
+        let total = self.total.load(Ordering::Relaxed);
+        if total == 0 {
+            return 0;
+        }
+        self.done.load(Ordering::Relaxed) * 100 / total
+    }
+}
+
+// files bigger than this get mapped instead of copied into memory
+const MMAP_THRESHOLD: u64 = 1 << 20;
+
+enum FileBytes {
+    Mapped(Mmap),
+    Read(Vec<u8>),
+}
+
+impl Deref for FileBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            FileBytes::Mapped(map) => map,
+            FileBytes::Read(bytes) => bytes,
+        }
+    }
+}
+
+fn read_bytes(path: &str) -> io::Result<FileBytes> {
+    let file = File::open(path)?;
+    if file.metadata()?.len() >= MMAP_THRESHOLD {
+        // safety: another process truncating the file while we index it can fault,
+        // same trade off every mmap based editor makes for big logs
+        if let Ok(map) = unsafe { Mmap::map(&file) } {
+            return Ok(FileBytes::Mapped(map));
+        }
+    }
+    fs::read(path).map(FileBytes::Read)
+}
+
+/** returns the lines as shared strings and the format to save them back with */
+pub fn load_file(path: String) -> LoadedFile {
+    load_file_with_progress(path, None, &LoadProgress::default())
+}
 
-    let mut file = file.unwrap();
-    let mut buf  = String::new();
-    let _ = file.read_to_string(&mut buf);
+/**
+ * same as load_file but reports how far along it is, meant for the background executor.
+ * `encoding` overrides detection
+ */
+pub fn load_file_with_progress(
+    path: String,
+    encoding: Option<&'static Encoding>,
+    progress: &LoadProgress,
+) -> LoadedFile {
+    let bytes = match read_bytes(&path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            println!("{}", path.clone() + " NOT FOUND, creataing");
+            let _ = File::create(&path);
+            FileBytes::Read(vec![])
+        }
+        Err(err) => {
+            return LoadedFile {
+                lines: vec!["".into()],
+                format: FileFormat::default(),
+                error: Some(format!("can't read {}: {}", path, err)),
+                read_only: Some(ReadOnly::Unreadable),
+            };
+        }
+    };
+    let decoded = match encoding {
+        Some(encoding) => decode_with(&bytes, encoding),
+        None => decode(&bytes),
+    };
+    decode_lines(decoded, progress)
+}
 
-    let parts: Vec<String> = buf.split(&"\n".to_string()).map(str::to_string).collect();
-    // convert to shared string
-    return parts.into_iter().map(Into::into).collect(); 
+/** an existing file, for edits that don't go through a buffer. unlike load_file never creates it */
+pub fn read_file(path: &Path) -> io::Result<LoadedFile> {
+    Ok(decode_lines(decode(&fs::read(path)?), &LoadProgress::default()))
 }
 
-pub fn save(path: String, lines: Vec<SharedString>) {
-    let file =  File::create(path.clone());
-    if file.is_err() {
-        println!("{}", path + " NOT FOUND");
+fn decode_lines(decoded: Decoded, progress: &LoadProgress) -> LoadedFile {
+    let (parts, final_newline) = split_lines(&decoded.text);
+    progress.total.store(parts.len(), Ordering::Relaxed);
+    let format = FileFormat {
+        line_ending: LineEnding::detect(&decoded.text),
+        final_newline,
+        encoding: decoded.encoding,
+        bom: decoded.bom,
+    };
+    let read_only = match (&decoded.error, decoded.lossy) {
+        (None, _) => None,
+        (Some(_), true) => Some(ReadOnly::Lossy),
+        (Some(_), false) => Some(ReadOnly::RoundTrip),
+    };
+    // convert to shared string, the only copy of each line we make
+    let lines = parts
+        .into_iter()
+        .map(|line| {
+            progress.done.fetch_add(1, Ordering::Relaxed);
+            SharedString::from(line.to_string())
+        })
+        .collect();
+    LoadedFile {
+        lines,
+        format,
+        read_only,
+        error: decoded.error.map(|err| format!("{}, opened read only", err)),
     }
-    let mut file = file.unwrap();
+}
+
+/** writes to a temp file next to the target then renames it over, so a failed write never touches the original */
+pub fn save(path: String, lines: Vec<SharedString>, format: FileFormat, backup: bool) -> io::Result<()> {
+    // write through symlinks instead of replacing the link with a regular file
+    let target = resolve_symlink(Path::new(&path))?;
+    let original = fs::metadata(&target).ok();
+
+    let dir = target.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let name = target.file_name().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not a file", path))
+    })?;
+    let tmp_path = dir.join(format!(".{}.{}.tmp", name.to_string_lossy(), std::process::id()));
+
+    let bytes = encode(&join_lines(&lines, format), format.encoding, format.bom)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+    let result = write_tmp(&tmp_path, &bytes, original.as_ref()).and_then(|_| {
+        if backup && original.is_some() {
+            let mut backup_path = target.clone().into_os_string();
+            backup_path.push("~");
+            fs::copy(&target, backup_path)?;
+        }
+        fs::rename(&tmp_path, &target)?;
+        sync_dir(dir)
+    });
+
+    if result.is_err() {
+        // don't leave half written files lying around
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+pub fn join_lines(lines: &[SharedString], format: FileFormat) -> String {
+    let mut text = String::new();
     let num_lines = lines.len();
-    for (i, line) in lines.into_iter().enumerate() {
-        let mut string: String = line.into();
-        if i != num_lines - 1 { // dont' append blank line 
-            string += "\n";
+    for (i, line) in lines.iter().enumerate() {
+        text.push_str(line);
+        if i != num_lines - 1 || format.final_newline {
+            text.push_str(format.line_ending.as_str());
+        }
+    }
+    text
+}
+
+fn write_tmp(tmp_path: &Path, bytes: &[u8], original: Option<&fs::Metadata>) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(tmp_path)?;
+    file.write_all(bytes)?;
+
+    if let Some(original) = original {
+        file.set_permissions(original.permissions())?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            // only root can give files away, so keeping the owner is best effort
+            let _ = std::os::unix::fs::fchown(&file, Some(original.uid()), Some(original.gid()));
         }
-        file.write(string.as_bytes()).expect("Unable to write data");
     }
-    println!("saved");
-}
\ No newline at end of file
+    file.sync_all()
+}
+
+fn resolve_symlink(path: &Path) -> io::Result<PathBuf> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_symlink() => fs::canonicalize(path),
+        _ => Ok(path.to_path_buf()),
+    }
+}
+
+// the rename is only durable once the directory entry is flushed
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use std::fmt;
+
+use encoding_rs::{Encoding, UTF_8};
+
+/** line ending a buffer is written back with, detected on load */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+    Cr,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+
+    /** vim style fileformat names, unix/dos/mac */
+    pub fn from_name(name: &str) -> Option<LineEnding> {
+        match name.to_lowercase().as_str() {
+            "unix" | "lf" => Some(LineEnding::Lf),
+            "dos" | "crlf" => Some(LineEnding::Crlf),
+            "mac" | "cr" => Some(LineEnding::Cr),
+            _ => None,
+        }
+    }
+
+    /** most common ending wins, ties and files without any go to LF */
+    pub fn detect(text: &str) -> LineEnding {
+        let (mut lf, mut crlf, mut cr) = (0, 0, 0);
+        let mut bytes = text.bytes().peekable();
+        while let Some(byte) = bytes.next() {
+            match byte {
+                b'\r' if bytes.peek() == Some(&b'\n') => {
+                    bytes.next();
+                    crlf += 1;
+                }
+                b'\r' => cr += 1,
+                b'\n' => lf += 1,
+                _ => {}
+            }
+        }
+
+        if crlf > lf && crlf >= cr {
+            LineEnding::Crlf
+        } else if cr > lf && cr > crlf {
+            LineEnding::Cr
+        } else {
+            LineEnding::Lf
+        }
+    }
+}
+
+impl fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LineEnding::Lf => "LF",
+            LineEnding::Crlf => "CRLF",
+            LineEnding::Cr => "CR",
+        })
+    }
+}
+
+/** splits on any of \n, \r\n and \r, returns the lines and whether the text ended with a line break */
+pub fn split_lines(text: &str) -> (Vec<&str>, bool) {
+    let mut lines = vec![];
+    let mut start = 0;
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\r' => {
+                lines.push(&text[start..i]);
+                if bytes.get(i + 1) == Some(&b'\n') {
+                    i += 1;
+                }
+                start = i + 1;
+            }
+            b'\n' => {
+                lines.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    let final_newline = !text.is_empty() && start == text.len();
+    if !final_newline {
+        // the last line has no break after it (or the file is empty)
+        lines.push(&text[start..]);
+    }
+    (lines, final_newline)
+}
+
+/** how a buffer maps back to bytes on disk */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileFormat {
+    pub line_ending: LineEnding,
+    pub final_newline: bool,
+    pub encoding: &'static Encoding,
+    pub bom: bool,
+}
+
+impl Default for FileFormat {
+    fn default() -> Self {
+        FileFormat {
+            line_ending: LineEnding::default(),
+            final_newline: false,
+            encoding: UTF_8,
+            bom: false,
+        }
+    }
+}
@@ -1 +1,4 @@
-pub mod files;
\ No newline at end of file
+pub mod files;
+pub mod format;
+pub mod encoding;
+pub mod watcher;
@@ -0,0 +1,58 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use futures::channel::mpsc::{unbounded, UnboundedReceiver};
+use notify::{recommended_watcher, RecommendedWatcher, RecursiveMode, Watcher};
+
+/** what the file looked like on disk the last time we read or wrote it */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DiskStamp {
+    pub modified: SystemTime,
+    pub len: u64,
+}
+
+impl DiskStamp {
+    pub fn read(path: &str) -> Option<DiskStamp> {
+        let meta = fs::metadata(path).ok()?;
+        Some(DiskStamp {
+            modified: meta.modified().ok()?,
+            len: meta.len(),
+        })
+    }
+}
+
+/**
+ * watches the directory instead of the file itself, atomic saves (ours, cargo fmt, git checkout)
+ * replace the inode and a watch on the old one goes quiet
+ */
+pub fn watch_file(path: &str) -> notify::Result<(RecommendedWatcher, UnboundedReceiver<()>)> {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+    let dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+    watch_dir(&dir, move |changed| *changed == path)
+}
+
+/** sends () whenever something in `dir` that passes `filter` changes, not recursive */
+pub fn watch_dir(
+    dir: &Path,
+    filter: impl Fn(&Path) -> bool + Send + 'static,
+) -> notify::Result<(RecommendedWatcher, UnboundedReceiver<()>)> {
+    let (tx, rx) = unbounded();
+
+    let mut watcher = recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        if event.kind.is_access() {
+            return;
+        }
+        if event.paths.iter().any(|changed| filter(changed)) {
+            let _ = tx.unbounded_send(());
+        }
+    })?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+    Ok((watcher, rx))
+}
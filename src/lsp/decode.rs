@@ -1,8 +1,304 @@
-use std::ops::Range;
+use std::{collections::HashMap, ops::Range, path::PathBuf, sync::Arc};
 
+use gpui::{Hsla, SharedString, ViewContext};
+use lsp_types::{DiagnosticSeverity, DiagnosticTag, NumberOrString, Position, PublishDiagnosticsParams};
+
+use crate::{
+    text::{
+        edit::{BufferEdit, BufferPoint},
+        text::TextInput,
+    },
+    theme::theme::Theme,
+};
+
+use super::lsp::uri_to_path;
+
+/** most severe first, so sorting puts errors on top */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl Severity {
+    pub fn color(self, theme: &Theme) -> Hsla {
+        match self {
+            Severity::Error => theme.error,
+            Severity::Warning => theme.warning,
+            Severity::Information => theme.info,
+            Severity::Hint => theme.hint,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Information => "info",
+            Severity::Hint => "hint",
+        }
+    }
+}
+
+/** another place the server points to, "first borrow here" etc */
+#[derive(Clone, Debug)]
+pub struct RelatedInfo {
+    pub path: Option<PathBuf>, // None for uris that aren't files
+    pub start: Position, // as the server sent it, the file may not be open
+    pub message: String,
+}
+
+/** one published diagnostic, points are byte columns in the buffer */
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub start: BufferPoint,
+    pub end: BufferPoint,
+    pub severity: Severity,
+    pub code: Option<String>,
+    pub source: Option<String>,
+    pub message: String,
+    pub unnecessary: bool, // unused code, drawn faded
+    pub deprecated: bool, // drawn struck through
+    pub related: Vec<RelatedInfo>,
+}
+
+/** the part of a diagnostic on one line, a diagnostic over several lines has one per line */
 #[derive(Clone)]
 pub struct Diagnostics {
-    pub diagnostic_range: Range<usize>,
-    pub is_error: bool, // else error
-    pub message: String,
-}
\ No newline at end of file
+    pub diagnostic_range: Range<usize>, // bytes on the line
+    pub diagnostic: Arc<Diagnostic>,
+}
+
+/** lsp columns count utf-16 units, clamped to the line and to a char boundary */
+pub fn byte_col(line: &str, utf16_col: u32) -> usize {
+    let mut units = 0;
+    for (offset, c) in line.char_indices() {
+        if units >= utf16_col as usize {
+            return offset;
+        }
+        units += c.len_utf16();
+    }
+    line.len()
+}
+
+fn point(content: &[SharedString], position: Position) -> BufferPoint {
+    let line = (position.line as usize).min(content.len().saturating_sub(1));
+    let text = content.get(line).map(|text| text.as_ref()).unwrap_or("");
+    // past the last line means the end of the buffer
+    let col = if position.line as usize >= content.len() { text.len() } else { byte_col(text, position.character) };
+    BufferPoint::new(line, col)
+}
+
+/** the params into buffer diagnostics, worst first, positions mapped against `content` */
+pub fn decode(params: PublishDiagnosticsParams, content: &[SharedString]) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = params
+        .diagnostics
+        .into_iter()
+        .map(|diagnostic| {
+            let tags = diagnostic.tags.unwrap_or_default();
+            Diagnostic {
+                start: point(content, diagnostic.range.start),
+                end: point(content, diagnostic.range.end),
+                // the spec leaves a missing severity to the client, most servers mean error
+                severity: match diagnostic.severity {
+                    Some(DiagnosticSeverity::WARNING) => Severity::Warning,
+                    Some(DiagnosticSeverity::INFORMATION) => Severity::Information,
+                    Some(DiagnosticSeverity::HINT) => Severity::Hint,
+                    _ => Severity::Error,
+                },
+                code: diagnostic.code.map(|code| match code {
+                    NumberOrString::Number(number) => number.to_string(),
+                    NumberOrString::String(code) => code,
+                }),
+                source: diagnostic.source,
+                message: diagnostic.message,
+                unnecessary: tags.contains(&DiagnosticTag::UNNECESSARY),
+                deprecated: tags.contains(&DiagnosticTag::DEPRECATED),
+                related: diagnostic
+                    .related_information
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|related| RelatedInfo {
+                        path: uri_to_path(&related.location.uri),
+                        start: related.location.range.start,
+                        message: related.message,
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+    diagnostics.sort_by_key(|diagnostic| (diagnostic.severity, diagnostic.start.line, diagnostic.start.col));
+    diagnostics
+}
+
+/**
+ * the per line pieces, keyed by line. an empty range grows to the char after it (or before
+ * it at the end of a line) so it can be seen
+ */
+pub fn by_line(diagnostics: Vec<Diagnostic>, content: &[SharedString]) -> HashMap<usize, Vec<Diagnostics>> {
+    let mut lines: HashMap<usize, Vec<Diagnostics>> = HashMap::new();
+    for diagnostic in diagnostics.into_iter().map(Arc::new) {
+        let (start, end) = (diagnostic.start, diagnostic.end);
+        for line in start.line..=end.line.max(start.line) {
+            let text = content[line].as_ref();
+            let from = if line == start.line { start.col } else { 0 };
+            let to = if line == end.line { end.col.max(from) } else { text.len() };
+            // ends at the start of the next line, nothing of it is there
+            if line > start.line && line == end.line && to == 0 {
+                continue;
+            }
+            let range = if from < to {
+                from..to
+            } else if let Some(c) = text[from..].chars().next() {
+                from..from + c.len_utf8()
+            } else if let Some(c) = text[..from].chars().next_back() {
+                from - c.len_utf8()..from
+            } else {
+                from..from
+            };
+            lines.entry(line).or_default().push(Diagnostics {
+                diagnostic_range: range,
+                diagnostic: diagnostic.clone(),
+            });
+        }
+    }
+    lines
+}
+
+impl TextInput {
+    /** a publish is the whole set for the buffer, the last one is replaced */
+    pub fn set_diagnostics(&mut self, params: PublishDiagnosticsParams, cx: &mut ViewContext<Self>) {
+        // computed for text we've edited since, its positions would land on the wrong lines
+        let current = self.lsp.as_ref().map(|document| document.version);
+        if params.version.zip(current).is_some_and(|(version, current)| version < current) {
+            return;
+        }
+        let diagnostics = decode(params, &self.content);
+        self.diagnostics = by_line(diagnostics, &self.content);
+        cx.notify();
+    }
+
+    /**
+     * after an edit, until the server publishes again. lines the edit touched lose theirs,
+     * the ones below move with the text
+     */
+    pub fn shift_diagnostics(&mut self, edit: &BufferEdit) {
+        if self.diagnostics.is_empty() {
+            return;
+        }
+        let (start, old_end, new_end) = (edit.start.line, edit.old_end.line, edit.new_end.line);
+        self.diagnostics = self
+            .diagnostics
+            .drain()
+            .filter(|(line, _)| *line < start || *line > old_end)
+            .map(|(line, parts)| if line > old_end { (line - old_end + new_end, parts) } else { (line, parts) })
+            .collect();
+    }
+
+    /** the worst diagnostic on the line, for the gutter and scrollbar */
+    pub fn line_severity(&self, line: usize) -> Option<Severity> {
+        self.diagnostics.get(&line)?.iter().map(|part| part.diagnostic.severity).min()
+    }
+
+    /** what the status line shows with the cursor on a diagnostic, `error[E0308] rustc: ...` */
+    pub fn diagnostic_status(&self) -> Option<SharedString> {
+        let cursor = self.cursor_offset();
+        let parts = self.diagnostics.get(&self.focused_line)?;
+        let part = parts
+            .iter()
+            .filter(|part| part.diagnostic_range.start <= cursor && cursor <= part.diagnostic_range.end)
+            .min_by_key(|part| part.diagnostic.severity)?;
+        let diagnostic = &part.diagnostic;
+        let mut status = diagnostic.severity.name().to_string();
+        if let Some(code) = &diagnostic.code {
+            status += &format!("[{}]", code);
+        }
+        if let Some(source) = &diagnostic.source {
+            status += &format!(" {}", source);
+        }
+        // multi line messages would push the status line around
+        status += &format!(": {}", diagnostic.message.lines().next().unwrap_or_default());
+        if !diagnostic.related.is_empty() {
+            status += &format!(" (+{} related)", diagnostic.related.len());
+        }
+        Some(status.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content(lines: &[&str]) -> Vec<SharedString> {
+        lines.iter().map(|line| line.to_string().into()).collect()
+    }
+
+    fn diagnostic(start: (usize, usize), end: (usize, usize)) -> Diagnostic {
+        Diagnostic {
+            start: BufferPoint::new(start.0, start.1),
+            end: BufferPoint::new(end.0, end.1),
+            severity: Severity::Error,
+            code: None,
+            source: None,
+            message: String::new(),
+            unnecessary: false,
+            deprecated: false,
+            related: vec![],
+        }
+    }
+
+    fn ranges(lines: &HashMap<usize, Vec<Diagnostics>>, line: usize) -> Vec<Range<usize>> {
+        let parts = lines.get(&line).map(Vec::as_slice).unwrap_or_default();
+        parts.iter().map(|part| part.diagnostic_range.clone()).collect()
+    }
+
+    #[test]
+    fn byte_col_counts_utf16_units() {
+        // é is one unit and two bytes, 😀 two units and four bytes
+        let line = "aé😀b";
+        assert_eq!(byte_col(line, 0), 0);
+        assert_eq!(byte_col(line, 1), 1);
+        assert_eq!(byte_col(line, 2), 3);
+        assert_eq!(byte_col(line, 4), 7);
+        assert_eq!(byte_col(line, 5), 8);
+    }
+
+    #[test]
+    fn byte_col_clamps() {
+        // inside the surrogate pair lands after the char, past the end on the end
+        assert_eq!(byte_col("a😀b", 2), 5);
+        assert_eq!(byte_col("abc", 99), 3);
+        assert_eq!(byte_col("", 3), 0);
+    }
+
+    #[test]
+    fn by_line_splits_multi_line_ranges() {
+        let content = content(&["abcd", "ef", "ghi"]);
+        let lines = by_line(vec![diagnostic((0, 2), (2, 1))], &content);
+        assert_eq!(ranges(&lines, 0), vec![2..4]);
+        assert_eq!(ranges(&lines, 1), vec![0..2]);
+        assert_eq!(ranges(&lines, 2), vec![0..1]);
+    }
+
+    #[test]
+    fn by_line_skips_an_end_at_the_next_line_start() {
+        let content = content(&["abcd", "ef"]);
+        let lines = by_line(vec![diagnostic((0, 1), (1, 0))], &content);
+        assert_eq!(ranges(&lines, 0), vec![1..4]);
+        assert!(!lines.contains_key(&1));
+    }
+
+    #[test]
+    fn by_line_widens_empty_ranges() {
+        let content = content(&["aéc", ""]);
+        let lines = by_line(vec![diagnostic((0, 1), (0, 1))], &content);
+        assert_eq!(ranges(&lines, 0), vec![1..3]);
+        // at the end of the line it takes the char before
+        let lines = by_line(vec![diagnostic((0, 4), (0, 4))], &content);
+        assert_eq!(ranges(&lines, 0), vec![3..4]);
+        let lines = by_line(vec![diagnostic((1, 0), (1, 0))], &content);
+        assert_eq!(ranges(&lines, 1), vec![0..0]);
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use futures::StreamExt;
+use gpui::{SharedString, ViewContext};
+use lsp_types::{
+    notification::{DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument, PublishDiagnostics},
+    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams, DidSaveTextDocumentParams,
+    Position, Range, TextDocumentContentChangeEvent, TextDocumentIdentifier, TextDocumentItem,
+    TextDocumentSyncKind, Uri, VersionedTextDocumentIdentifier,
+};
+
+use crate::text::{
+    edit::{BufferEdit, BufferPoint},
+    text::TextInput,
+};
+
+use super::{
+    lsp::path_to_uri,
+    servers::{server_for, LanguageServer},
+};
+
+/** lsp columns count utf-16 code units */
+pub fn utf16_len(text: &str) -> usize {
+    text.chars().map(char::len_utf16).sum()
+}
+
+fn full_text(content: &[SharedString]) -> String {
+    content.iter().map(|line| line.as_ref()).collect::<Vec<&str>>().join("\n")
+}
+
+/** what the buffer holds from `start` to `end`, lines joined with \n like the server sees them */
+pub fn text_between(content: &[SharedString], start: BufferPoint, end: BufferPoint) -> String {
+    if start.line == end.line {
+        return content[start.line][start.col..end.col].to_string();
+    }
+    let mut text = content[start.line][start.col..].to_string();
+    for line in &content[start.line + 1..end.line] {
+        text.push('\n');
+        text.push_str(line);
+    }
+    text.push('\n');
+    text.push_str(&content[end.line][..end.col]);
+    text
+}
+
+/** a buffer open on a server, every edit bumps the version. closed on drop */
+pub struct LspDocument {
+    pub server: Arc<LanguageServer>,
+    pub uri: Uri,
+    pub version: i32,
+}
+
+impl LspDocument {
+    pub fn open(server: Arc<LanguageServer>, uri: Uri, language_id: &str, content: &[SharedString]) -> Result<Self, String> {
+        server.client.notify::<DidOpenTextDocument>(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(uri.clone(), language_id.to_string(), 1, full_text(content)),
+        })?;
+        Ok(LspDocument { server, uri, version: 1 })
+    }
+
+    fn send_change(&mut self, change: TextDocumentContentChangeEvent) {
+        self.version += 1;
+        let sent = self.server.client.notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(self.uri.clone(), self.version),
+            content_changes: vec![change],
+        });
+        if let Err(err) = sent {
+            eprintln!("lsp: {}", err);
+        }
+    }
+
+    /** call after `content` took the edit */
+    pub fn changed(&mut self, edit: &BufferEdit, content: &[SharedString]) {
+        let change = match self.server.sync_kind() {
+            TextDocumentSyncKind::INCREMENTAL => {
+                let start = Position::new(edit.start.line as u32, utf16_len(&content[edit.start.line][..edit.start.col]) as u32);
+                let old_end = Position::new(edit.old_end.line as u32, edit.old_end_utf16 as u32);
+                TextDocumentContentChangeEvent {
+                    range: Some(Range::new(start, old_end)),
+                    range_length: None,
+                    text: text_between(content, edit.start, edit.new_end),
+                }
+            }
+            TextDocumentSyncKind::FULL => TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: full_text(content),
+            },
+            _ => return,
+        };
+        self.send_change(change);
+    }
+
+    /** whole buffer replaced, reload etc */
+    pub fn replaced(&mut self, content: &[SharedString]) {
+        if self.server.sync_kind() == TextDocumentSyncKind::NONE {
+            return;
+        }
+        self.send_change(TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: full_text(content),
+        });
+    }
+
+    pub fn saved(&self, content: &[SharedString]) {
+        let Some(include_text) = self.server.save_text() else {
+            return;
+        };
+        let sent = self.server.client.notify::<DidSaveTextDocument>(DidSaveTextDocumentParams {
+            text_document: TextDocumentIdentifier::new(self.uri.clone()),
+            text: include_text.then(|| full_text(content)),
+        });
+        if let Err(err) = sent {
+            eprintln!("lsp: {}", err);
+        }
+    }
+}
+
+impl Drop for LspDocument {
+    fn drop(&mut self) {
+        let _ = self.server.client.notify::<DidCloseTextDocument>(DidCloseTextDocumentParams {
+            text_document: TextDocumentIdentifier::new(self.uri.clone()),
+        });
+    }
+}
+
+impl TextInput {
+    /**
+     * opens the buffer on the server for its language and workspace once that has started,
+     * then keeps the diagnostics the server publishes for it. no server for the language, no lsp
+     */
+    pub fn attach_lsp(&mut self, cx: &mut ViewContext<Self>) {
+        // servers compare uris as strings, `.` and `//` would make a different document
+        let path: PathBuf = Path::new(&self.open_file).components().collect();
+        let Some(uri) = path_to_uri(&path) else {
+            return;
+        };
+        let first_line = self.content.first().map(|line| line.to_string()).unwrap_or_default();
+        let Some((language, server)) = server_for(&path, &first_line, cx) else {
+            return;
+        };
+
+        self.lsp_task = Some(cx.spawn(|this, mut cx| async move {
+            let server = match server.await {
+                Ok(server) => server,
+                Err(err) => {
+                    let _ = this.update(&mut cx, |this, cx| {
+                        this.status_message = Some(err.into());
+                        cx.notify();
+                    });
+                    return;
+                }
+            };
+            // subscribed before didOpen so the first publish isn't missed
+            let mut diagnostics = server.client.notifications::<PublishDiagnostics>();
+            let opened = this.update(&mut cx, |this, cx| {
+                match LspDocument::open(server.clone(), uri.clone(), &language, &this.content) {
+                    Ok(document) => this.lsp = Some(document),
+                    Err(err) => {
+                        this.status_message = Some(format!("lsp: {}", err).into());
+                        cx.notify();
+                    }
+                }
+                this.lsp.is_some()
+            });
+            if !matches!(opened, Ok(true)) {
+                return;
+            }
+
+            // ends when the server exits
+            while let Some(params) = diagnostics.next().await {
+                if params.uri != uri {
+                    continue;
+                }
+                if this.update(&mut cx, |this, cx| this.set_diagnostics(params, cx)).is_err() {
+                    break;
+                }
+            }
+        }));
+    }
+}
@@ -1,163 +1,118 @@
 use std::{
-    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
     process::{self, Command, Stdio},
     str::FromStr,
 };
+
 use lsp_types::{
-    *,
+    notification::Initialized,
+    request::Initialize,
+    ClientCapabilities, ClientInfo, DiagnosticTag, InitializeParams, InitializeResult,
+    InitializedParams, PublishDiagnosticsClientCapabilities, TagSupport, TextDocumentClientCapabilities,
+    TextDocumentSyncClientCapabilities, Uri, WindowClientCapabilities, WorkspaceClientCapabilities, WorkspaceFolder,
 };
-use serde_json::{from_str, json, to_string, value::to_raw_value, Value};
-
-fn read_message<R: Read>(reader: &mut BufReader<R>) -> std::io::Result<Option<String>> {
-    let mut header = String::new();
-    let mut content_length: usize = 0;
-
-    // Read headers
-    loop {
-        header.clear();
-        if reader.read_line(&mut header)? == 0 {
-            return Ok(None);
-        }
-        
-        let header = header.trim();
-        if header.is_empty() {
-            break;
-        }
-        
-        if let Some(length_str) = header.strip_prefix("Content-Length: ") {
-            content_length = length_str.parse().unwrap_or(0);
+use serde_json::Value;
+
+use super::rpc::RpcClient;
+
+/** file:// uri for an absolute path, everything but unreserved bytes and `/` is escaped */
+pub fn path_to_uri(path: &Path) -> Option<Uri> {
+    let path = path.to_str()?;
+    let mut uri = String::from("file://");
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
         }
     }
-
-    // Read content
-    let mut content = vec![0; content_length];
-    reader.read_exact(&mut content)?;
-    
-    Ok(Some(String::from_utf8_lossy(&content).into_owned()))
-}
-
-fn get_init(directory: String) -> Option<String> {
-    #[allow(deprecated)]
-    let initialize_params = InitializeParams {
-        process_id: Some(process::id()),
-        workspace_folders: None,
-        initialization_options: None,
-        capabilities: ClientCapabilities::default(),
-        trace: Some(TraceValue::Verbose),
-        client_info: None,
-        locale: None,
-        work_done_progress_params: WorkDoneProgressParams::default(),
-        root_path: None,
-        root_uri: Some(Uri::from_str(&("file://".to_owned() + &directory)).unwrap()),
-    };
-
-    let to_value = serde_json::to_value(initialize_params);
-    let raw_value = to_raw_value(&to_value.unwrap()).unwrap();
-    let initialize_request = jsonrpc::Request {
-        jsonrpc: Some("2.0"),
-        id: 1.into(),
-        method: "initialize",
-        params: Some(&raw_value),
-    };
-
-    let request_json = to_string(&initialize_request).ok()?;
-    let request_message = format!("Content-Length: {}\r\n\r\n{}", request_json.len(), request_json);
-    Some(request_message)
+    Uri::from_str(&uri).ok()
 }
 
-#[derive(Debug)]
-pub struct Lsp {
-    directory: String,
-    stdin: process::ChildStdin,
-    stdout_reader: BufReader<process::ChildStdout>,
+pub fn uri_to_path(uri: &Uri) -> Option<PathBuf> {
+    let path = uri.as_str().strip_prefix("file://")?;
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| path.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
 }
 
-pub fn start_lsp(directory: String) -> Lsp {
-    let lsp_command = Command::new("rust-analyzer")
-        .stdout(Stdio::piped())
+/** starts the server with piped stdio, an error if it isn't installed */
+pub fn spawn_server(command: &str, args: &[String], root: &Path) -> Result<RpcClient, String> {
+    let child = Command::new(command)
+        .args(args)
+        .current_dir(root)
         .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
         .stderr(Stdio::piped())
-        .spawn().unwrap();
-
-    let stdin = lsp_command.stdin.expect("Failed to open stdin");
-    let stdout = lsp_command.stdout.expect("Failed to open stdout");
-    let stdout_reader = BufReader::new(stdout);
-
-    let mut lsp = Lsp { 
-        directory: directory.clone(), 
-        stdin,
-        stdout_reader,
-    };
-
-    // let stderr = lsp_command.stderr.take().expect("Failed to open stderr");
-
-    let init_request = get_init(directory).unwrap();
-    println!("{}", init_request);
-    lsp.stdin.write_all(init_request.as_bytes()).unwrap();
-    lsp.stdin.flush().unwrap();
-
-    // Handle stdout messages
-    if read_message(&mut lsp.stdout_reader).is_ok() {
-        // Notify that we have initalized
-        let initialized_notification = json!({
-            "jsonrpc": "2.0",
-            "method": "initialized",
-            "params": {}
-        }).to_string();
-
-        let message = format!("Content-Length: {}\r\n\r\n{}", initialized_notification.len(), initialized_notification);
-        lsp.stdin.write_all(message.as_bytes()).unwrap();
-        lsp.stdin.flush().unwrap();
-
-        let file_path = "./main.rs";
-        let file_content = std::fs::read_to_string(file_path).unwrap();
-        let did_open_request = format!(
-            r#"{{
-                "jsonrpc": "2.0",
-                "method": "textDocument/didOpen",
-                "params": {{
-                    "textDocument": {{
-                        "uri": "file://{0}",
-                        "languageId": "rs",
-                        "version": 1,
-                        "text": "{1}"
-                    }}
-                }}
-            }}"#,
-            file_path,
-            file_content.replace('\n', "\\n").replace('"', "\\\"")
-        );
-
-        let did_open_message = format!(
-            "Content-Length: {}\r\n\r\n{}",
-            did_open_request.len(),
-            did_open_request
-        );
-
-        lsp.stdin.write_all(did_open_message.as_bytes()).unwrap();
-        lsp.stdin.flush().unwrap();
-    }
-
-    lsp
+        .spawn()
+        .map_err(|err| format!("{}: {}", command, err))?;
+    RpcClient::new(command, child)
 }
 
-// lsp, diagnostics
-pub async fn run_lsp(mut lsp: Lsp) -> std::io::Result<(Lsp, Option<Value>)> {
-    println!("Waiting for diagnostics...");
-    while let Ok(Some(message)) = read_message(&mut lsp.stdout_reader) {
-        // Try to parse as PublishDiagnostics notification
-        if let Ok(notification) = from_str::<serde_json::Value>(&message) {
-            if notification["method"] == "textDocument/publishDiagnostics" {
-                println!("Received diagnostics:");
-                // println!("{}", serde_json::to_string_pretty(&notification).unwrap());
-
-                return Ok((lsp, Some(notification)));
-            }
-            // else
-            return Ok((lsp, None))
-        }
+fn client_capabilities() -> ClientCapabilities {
+    ClientCapabilities {
+        workspace: Some(WorkspaceClientCapabilities {
+            configuration: Some(true),
+            workspace_folders: Some(true),
+            ..Default::default()
+        }),
+        text_document: Some(TextDocumentClientCapabilities {
+            synchronization: Some(TextDocumentSyncClientCapabilities {
+                did_save: Some(true),
+                ..Default::default()
+            }),
+            publish_diagnostics: Some(PublishDiagnosticsClientCapabilities {
+                related_information: Some(true),
+                tag_support: Some(TagSupport {
+                    value_set: vec![DiagnosticTag::UNNECESSARY, DiagnosticTag::DEPRECATED],
+                }),
+                code_description_support: Some(true),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        window: Some(WindowClientCapabilities {
+            work_done_progress: Some(true),
+            ..Default::default()
+        }),
+        ..Default::default()
     }
+}
 
-    // lsp.wait()?;
-    Ok((lsp, None))
-}
\ No newline at end of file
+/** the initialize handshake, nothing else may be sent before it resolves */
+pub async fn initialize(client: &RpcClient, root: &Path, initialization_options: Option<Value>) -> Result<InitializeResult, String> {
+    let root_uri = path_to_uri(root).ok_or_else(|| format!("{}: not a valid uri", root.display()))?;
+    let name = root.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    #[allow(deprecated)]
+    let params = InitializeParams {
+        process_id: Some(process::id()),
+        root_uri: Some(root_uri.clone()),
+        workspace_folders: Some(vec![WorkspaceFolder { uri: root_uri, name }]),
+        initialization_options,
+        capabilities: client_capabilities(),
+        client_info: Some(ClientInfo {
+            name: env!("CARGO_PKG_NAME").to_string(),
+            version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        }),
+        ..Default::default()
+    };
+    let result = client.request::<Initialize>(params).await?;
+    client.notify::<Initialized>(InitializedParams {})?;
+    Ok(result)
+}
@@ -1,2 +1,6 @@
 pub mod lsp;
+pub mod rpc;
+pub mod registry;
+pub mod servers;
+pub mod document;
 pub mod decode;
\ No newline at end of file
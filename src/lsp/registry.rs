@@ -0,0 +1,210 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+use crate::settings::settings::LanguageServerSettings;
+
+/** a language, how to recognise its files and which server to start for it */
+#[derive(Clone, Debug)]
+pub struct LanguageEntry {
+    pub name: String, // the lsp language id
+    pub extensions: Vec<String>,
+    pub filenames: Vec<String>, // exact names, .bashrc etc
+    pub shebangs: Vec<String>, // interpreters without version, python for python3.11
+    pub command: Option<String>, // None, or off in the settings, means no server
+    pub args: Vec<String>,
+    pub initialization_options: Option<Value>,
+    pub root_markers: Vec<String>, // the nearest directory up holding one is the workspace root
+}
+
+fn entry(name: &str, extensions: &[&str], command: &str, args: &[&str], root_markers: &[&str]) -> LanguageEntry {
+    let strings = |list: &[&str]| -> Vec<String> { list.iter().map(|item| item.to_string()).collect() };
+    LanguageEntry {
+        name: name.to_string(),
+        extensions: strings(extensions),
+        filenames: vec![],
+        shebangs: vec![],
+        command: Some(command.to_string()),
+        args: strings(args),
+        initialization_options: None,
+        root_markers: strings(root_markers),
+    }
+}
+
+fn with_names(mut entry: LanguageEntry, filenames: &[&str], shebangs: &[&str]) -> LanguageEntry {
+    entry.filenames = filenames.iter().map(|name| name.to_string()).collect();
+    entry.shebangs = shebangs.iter().map(|name| name.to_string()).collect();
+    entry
+}
+
+/** what works without any config, as long as the server is on PATH */
+fn defaults() -> Vec<LanguageEntry> {
+    let clangd_markers = &["compile_commands.json", "compile_flags.txt", ".clangd"];
+    let node_markers = &["tsconfig.json", "jsconfig.json", "package.json"];
+    vec![
+        entry("rust", &["rs"], "rust-analyzer", &[], &["Cargo.toml"]),
+        entry("c", &["c", "h"], "clangd", &[], clangd_markers),
+        entry("cpp", &["cc", "cpp", "cxx", "hh", "hpp", "hxx"], "clangd", &[], clangd_markers),
+        with_names(
+            entry(
+                "python",
+                &["py", "pyi"],
+                "pyright-langserver",
+                &["--stdio"],
+                &["pyproject.toml", "setup.py", "setup.cfg", "requirements.txt", "pyrightconfig.json"],
+            ),
+            &[],
+            &["python"],
+        ),
+        entry("go", &["go"], "gopls", &[], &["go.work", "go.mod"]),
+        with_names(
+            entry("javascript", &["js", "mjs", "cjs"], "typescript-language-server", &["--stdio"], node_markers),
+            &[],
+            &["node"],
+        ),
+        entry("javascriptreact", &["jsx"], "typescript-language-server", &["--stdio"], node_markers),
+        entry("typescript", &["ts", "mts", "cts"], "typescript-language-server", &["--stdio"], node_markers),
+        entry("typescriptreact", &["tsx"], "typescript-language-server", &["--stdio"], node_markers),
+        entry("zig", &["zig"], "zls", &[], &["build.zig"]),
+        with_names(
+            entry("lua", &["lua"], "lua-language-server", &[], &[".luarc.json"]),
+            &[],
+            &["lua"],
+        ),
+        with_names(
+            entry("shellscript", &["sh", "bash"], "bash-language-server", &["start"], &[]),
+            &[".bashrc", ".bash_profile", ".profile", "PKGBUILD"],
+            &["sh", "bash"],
+        ),
+        entry("json", &["json"], "vscode-json-language-server", &["--stdio"], &[]),
+        with_names(
+            entry("toml", &["toml"], "taplo", &["lsp", "stdio"], &[]),
+            &["Cargo.lock"],
+            &[],
+        ),
+        entry("nix", &["nix"], "nil", &[], &["flake.nix"]),
+    ]
+}
+
+/** `#!/usr/bin/env python3.11 -u` -> python */
+fn interpreter(first_line: &str) -> Option<&str> {
+    let mut words = first_line.strip_prefix("#!")?.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+    }
+    Some(program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.'))
+}
+
+/** the built in languages with the settings' `language_servers` laid over them */
+pub struct Registry {
+    pub languages: Vec<LanguageEntry>,
+}
+
+impl Registry {
+    pub fn new(overrides: &HashMap<String, LanguageServerSettings>) -> Self {
+        let mut languages = defaults();
+        for (name, settings) in overrides {
+            let index = match languages.iter().position(|language| &language.name == name) {
+                Some(index) => index,
+                None => {
+                    languages.push(LanguageEntry {
+                        name: name.clone(),
+                        extensions: vec![],
+                        filenames: vec![],
+                        shebangs: vec![],
+                        command: None,
+                        args: vec![],
+                        initialization_options: None,
+                        root_markers: vec![],
+                    });
+                    languages.len() - 1
+                }
+            };
+            let language = &mut languages[index];
+            if let Some(command) = &settings.command {
+                language.command = Some(command.clone());
+            }
+            if !settings.enabled.unwrap_or(true) {
+                language.command = None;
+            }
+            if let Some(extensions) = &settings.extensions {
+                language.extensions = extensions.clone();
+            }
+            if let Some(filenames) = &settings.filenames {
+                language.filenames = filenames.clone();
+            }
+            if let Some(shebangs) = &settings.shebangs {
+                language.shebangs = shebangs.clone();
+            }
+            if let Some(args) = &settings.args {
+                language.args = args.clone();
+            }
+            if let Some(root_markers) = &settings.root_markers {
+                language.root_markers = root_markers.clone();
+            }
+            if settings.initialization_options.is_some() {
+                language.initialization_options = settings.initialization_options.clone();
+            }
+        }
+        Registry { languages }
+    }
+
+    /** by exact filename, then extension, then the shebang on the first line */
+    pub fn language_for(&self, path: &Path, first_line: &str) -> Option<&LanguageEntry> {
+        let filename = path.file_name().and_then(|name| name.to_str());
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        let interpreter = interpreter(first_line);
+        self.languages
+            .iter()
+            .find(|language| filename.is_some_and(|name| language.filenames.iter().any(|known| known == name)))
+            .or_else(|| {
+                self.languages
+                    .iter()
+                    .find(|language| extension.is_some_and(|extension| language.extensions.iter().any(|known| known == extension)))
+            })
+            .or_else(|| {
+                self.languages
+                    .iter()
+                    .find(|language| interpreter.is_some_and(|program| language.shebangs.iter().any(|known| known == program)))
+            })
+    }
+}
+
+/**
+ * nearest directory above the file holding one of the markers. without one the project
+ * root when the file is inside it, else the file's own directory
+ */
+pub fn workspace_root(language: &LanguageEntry, path: &Path, project_root: &Path) -> PathBuf {
+    let directory = path.parent().unwrap_or(path);
+    let marked = directory
+        .ancestors()
+        .find(|dir| language.root_markers.iter().any(|marker| dir.join(marker).exists()));
+    match marked {
+        Some(dir) => dir.to_path_buf(),
+        None if path.starts_with(project_root) => project_root.to_path_buf(),
+        None => directory.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpreter_from_shebang() {
+        assert_eq!(interpreter("#!/usr/bin/env python3.11 -u"), Some("python"));
+        assert_eq!(interpreter("#!/usr/bin/python3"), Some("python"));
+        assert_eq!(interpreter("#!/bin/bash"), Some("bash"));
+        assert_eq!(interpreter("#! /usr/bin/env -S NODE_OPTIONS=x node --flag"), Some("node"));
+    }
+
+    #[test]
+    fn interpreter_needs_a_shebang() {
+        assert_eq!(interpreter("fn main() {}"), None);
+        assert_eq!(interpreter("#!/usr/bin/env"), None);
+    }
+}
@@ -0,0 +1,290 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    io::{self, BufRead, BufReader, Write},
+    process::Child,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        mpsc as std_mpsc, Arc, Mutex,
+    },
+    thread,
+};
+
+use futures::{
+    channel::{mpsc, oneshot},
+    stream::LocalBoxStream,
+    StreamExt,
+};
+use lsp_types::{notification::Notification, request::Request};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+pub const METHOD_NOT_FOUND: i64 = -32601;
+const SERVER_EXITED: i64 = -32099; // in the range json-rpc leaves to implementations
+
+/** the error member of a response, ours or the server's */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResponseError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+type PendingResponse = oneshot::Sender<Result<Value, ResponseError>>;
+type RequestHandler = Box<dyn Fn(Value) -> Result<Value, ResponseError> + Send>;
+
+/** one `Content-Length` framed message, None once the stream ends */
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<String>> {
+    let mut header = String::new();
+    let mut content_length: Option<usize> = None;
+
+    loop {
+        header.clear();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        // header names are case insensitive, Content-Type is the only other one and always utf-8
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let content_length = content_length
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "message without Content-Length"))?;
+    let mut content = vec![0; content_length];
+    reader.read_exact(&mut content)?;
+    Ok(Some(String::from_utf8_lossy(&content).into_owned()))
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n", body.len())?;
+    writer.write_all(body.as_bytes())?;
+    writer.flush()
+}
+
+/** what the server can ask of us without anyone having registered a handler */
+fn default_response(method: &str, params: &Value) -> Result<Value, ResponseError> {
+    match method {
+        // no settings of our own, null per item means the server's defaults
+        "workspace/configuration" => {
+            let items = params["items"].as_array().map_or(0, Vec::len);
+            Ok(Value::Array(vec![Value::Null; items]))
+        }
+        "client/registerCapability"
+        | "client/unregisterCapability"
+        | "window/workDoneProgress/create"
+        | "window/showMessageRequest"
+        | "workspace/diagnostic/refresh"
+        | "workspace/semanticTokens/refresh"
+        | "workspace/inlayHint/refresh"
+        | "workspace/codeLens/refresh" => Ok(Value::Null),
+        "workspace/applyEdit" => Ok(json!({ "applied": false, "failureReason": "not supported" })),
+        _ => Err(ResponseError {
+            code: METHOD_NOT_FOUND,
+            message: format!("unhandled method {}", method),
+            data: None,
+        }),
+    }
+}
+
+// shared between the client and its reader thread
+#[derive(Default)]
+struct Dispatch {
+    pending: Mutex<HashMap<i64, PendingResponse>>, // by request id
+    subscribers: Mutex<HashMap<String, Vec<mpsc::UnboundedSender<Value>>>>, // notification params by method
+    handlers: Mutex<HashMap<String, RequestHandler>>, // server to client requests by method
+}
+
+impl Dispatch {
+    fn handle(&self, message: Value, outgoing: &std_mpsc::Sender<Value>) {
+        let id = message.get("id").cloned();
+        match (message.get("method").and_then(Value::as_str), id) {
+            // a response to one of ours
+            (None, Some(id)) => {
+                let Some(sender) = id.as_i64().and_then(|id| self.pending.lock().unwrap().remove(&id)) else {
+                    eprintln!("lsp: response to unknown request {}", id);
+                    return;
+                };
+                let result = match message.get("error") {
+                    Some(error) => Err(serde_json::from_value(error.clone()).unwrap_or_else(|_| ResponseError {
+                        code: 0,
+                        message: error.to_string(),
+                        data: None,
+                    })),
+                    None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+                };
+                let _ = sender.send(result);
+            }
+            // the server asking something, always gets an answer
+            (Some(method), Some(id)) => {
+                let params = message.get("params").cloned().unwrap_or(Value::Null);
+                let result = match self.handlers.lock().unwrap().get(method) {
+                    Some(handler) => handler(params),
+                    None => default_response(method, &params),
+                };
+                let response = match result {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+                };
+                let _ = outgoing.send(response);
+            }
+            (Some(method), None) => {
+                let params = message.get("params").cloned().unwrap_or(Value::Null);
+                if let Some(subscribers) = self.subscribers.lock().unwrap().get_mut(method) {
+                    // dropped receivers unsubscribe
+                    subscribers.retain(|subscriber| subscriber.unbounded_send(params.clone()).is_ok());
+                }
+            }
+            (None, None) => eprintln!("lsp: message without method or id: {}", message),
+        }
+    }
+
+    /** the server is gone, whoever still waits gets an error and streams end */
+    fn close(&self) {
+        for (_, sender) in self.pending.lock().unwrap().drain() {
+            let _ = sender.send(Err(ResponseError {
+                code: SERVER_EXITED,
+                message: "server exited".to_string(),
+                data: None,
+            }));
+        }
+        self.subscribers.lock().unwrap().clear();
+    }
+}
+
+/**
+ * json-rpc over a server's stdin/stdout. a reader thread routes responses to whoever awaits
+ * them and notifications to subscribers, a writer thread drains the outgoing queue, so
+ * nothing here blocks and the futures work on any executor
+ */
+pub struct RpcClient {
+    pub name: String,
+    next_id: AtomicI64,
+    outgoing: std_mpsc::Sender<Value>,
+    dispatch: Arc<Dispatch>,
+    child: Mutex<Child>,
+}
+
+impl RpcClient {
+    pub fn new(name: &str, mut child: Child) -> Result<Self, String> {
+        let (Some(mut stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(format!("{}: stdin/stdout not piped", name));
+        };
+        let (outgoing, queue) = std_mpsc::channel::<Value>();
+        let dispatch = Arc::new(Dispatch::default());
+
+        thread::Builder::new()
+            .name(format!("{} writer", name))
+            .spawn(move || {
+                for message in queue {
+                    if let Err(err) = write_message(&mut stdin, &message) {
+                        eprintln!("lsp: write failed: {}", err);
+                        break;
+                    }
+                }
+            })
+            .map_err(|err| err.to_string())?;
+
+        let (reader_dispatch, reader_outgoing, reader_name) = (dispatch.clone(), outgoing.clone(), name.to_string());
+        thread::Builder::new()
+            .name(format!("{} reader", name))
+            .spawn(move || {
+                let mut reader = BufReader::new(stdout);
+                loop {
+                    match read_message(&mut reader) {
+                        Ok(Some(message)) => match serde_json::from_str(&message) {
+                            Ok(message) => reader_dispatch.handle(message, &reader_outgoing),
+                            Err(err) => eprintln!("{}: unparseable message: {}", reader_name, err),
+                        },
+                        Ok(None) => break,
+                        Err(err) => {
+                            eprintln!("{}: read failed: {}", reader_name, err);
+                            break;
+                        }
+                    }
+                }
+                reader_dispatch.close();
+            })
+            .map_err(|err| err.to_string())?;
+
+        // servers log a lot to stderr, a full pipe would block them
+        if let Some(stderr) = child.stderr.take() {
+            let _ = thread::Builder::new().name(format!("{} stderr", name)).spawn(move || {
+                for _ in BufReader::new(stderr).lines().map_while(Result::ok) {}
+            });
+        }
+
+        Ok(RpcClient {
+            name: name.to_string(),
+            next_id: AtomicI64::new(1),
+            outgoing,
+            dispatch,
+            child: Mutex::new(child),
+        })
+    }
+
+    fn send(&self, message: Value) -> Result<(), String> {
+        self.outgoing.send(message).map_err(|_| format!("{} is not running", self.name))
+    }
+
+    /** sent straight away, the result arrives whenever the server answers */
+    pub fn request<R: Request>(&self, params: R::Params) -> impl Future<Output = Result<R::Result, String>> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.dispatch.pending.lock().unwrap().insert(id, sender);
+        let sent = self.send(json!({ "jsonrpc": "2.0", "id": id, "method": R::METHOD, "params": params }));
+        if sent.is_err() {
+            self.dispatch.pending.lock().unwrap().remove(&id);
+        }
+        async move {
+            sent?;
+            match receiver.await {
+                Ok(Ok(result)) => serde_json::from_value(result).map_err(|err| format!("{}: {}", R::METHOD, err)),
+                Ok(Err(err)) => Err(format!("{}: {}", R::METHOD, err.message)),
+                Err(_) => Err(format!("{}: no response", R::METHOD)),
+            }
+        }
+    }
+
+    pub fn notify<N: Notification>(&self, params: N::Params) -> Result<(), String> {
+        self.send(json!({ "jsonrpc": "2.0", "method": N::METHOD, "params": params }))
+    }
+
+    /** every `N` from now on, ends when the server exits. params that don't decode are dropped */
+    pub fn notifications<N: Notification>(&self) -> LocalBoxStream<'static, N::Params> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.dispatch.subscribers.lock().unwrap().entry(N::METHOD.to_string()).or_default().push(sender);
+        receiver
+            .filter_map(|params| async move {
+                serde_json::from_value(params).map_err(|err| eprintln!("lsp: bad {}: {}", N::METHOD, err)).ok()
+            })
+            .boxed_local()
+    }
+
+    /** answers `R` from the server instead of the default response */
+    pub fn on_request<R: Request>(&self, handler: impl Fn(R::Params) -> Result<R::Result, ResponseError> + Send + 'static) {
+        let handler = move |params: Value| {
+            let params = serde_json::from_value(params).map_err(|err| ResponseError {
+                code: -32602, // invalid params
+                message: err.to_string(),
+                data: None,
+            })?;
+            handler(params).map(|result| serde_json::to_value(result).unwrap_or(Value::Null))
+        };
+        self.dispatch.handlers.lock().unwrap().insert(R::METHOD.to_string(), Box::new(handler));
+    }
+}
+
+impl Drop for RpcClient {
+    fn drop(&mut self) {
+        let _ = self.child.lock().unwrap().kill();
+    }
+}
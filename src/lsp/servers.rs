@@ -0,0 +1,98 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use futures::{future::Shared, FutureExt};
+use gpui::{AppContext, Global, Task};
+use lsp_types::{ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, TextDocumentSyncSaveOptions};
+
+use crate::{project::project::project, settings::settings::settings};
+
+use super::{
+    lsp::{initialize, spawn_server},
+    registry::{workspace_root, LanguageEntry, Registry},
+    rpc::RpcClient,
+};
+
+/** a running server past the initialize handshake */
+pub struct LanguageServer {
+    pub client: RpcClient,
+    pub capabilities: ServerCapabilities,
+}
+
+impl LanguageServer {
+    pub fn sync_kind(&self) -> TextDocumentSyncKind {
+        match &self.capabilities.text_document_sync {
+            Some(TextDocumentSyncCapability::Kind(kind)) => *kind,
+            Some(TextDocumentSyncCapability::Options(options)) => options.change.unwrap_or(TextDocumentSyncKind::NONE),
+            None => TextDocumentSyncKind::NONE,
+        }
+    }
+
+    /** None when the server doesn't want didSave, else whether it wants the text with it */
+    pub fn save_text(&self) -> Option<bool> {
+        let Some(TextDocumentSyncCapability::Options(options)) = &self.capabilities.text_document_sync else {
+            return None;
+        };
+        match options.save.as_ref()? {
+            TextDocumentSyncSaveOptions::Supported(supported) => supported.then_some(false),
+            TextDocumentSyncSaveOptions::SaveOptions(save) => Some(save.include_text.unwrap_or(false)),
+        }
+    }
+}
+
+pub type PendingServer = Shared<Task<Result<Arc<LanguageServer>, String>>>;
+
+/**
+ * one server per language and workspace root, started on first use and shared by every
+ * buffer that maps to it. buffers await the same start
+ */
+pub struct LanguageServers {
+    pub registry: Registry,
+    servers: HashMap<(String, PathBuf), PendingServer>,
+}
+
+impl Global for LanguageServers {}
+
+pub fn init(cx: &mut AppContext) {
+    let registry = Registry::new(&settings(cx).language_servers);
+    cx.set_global(LanguageServers {
+        registry,
+        servers: HashMap::new(),
+    });
+}
+
+fn start(language: &LanguageEntry, command: String, root: PathBuf, cx: &mut AppContext) -> PendingServer {
+    let (name, args, options) = (language.name.clone(), language.args.clone(), language.initialization_options.clone());
+    cx.spawn(|_| async move {
+        let client = spawn_server(&command, &args, &root).map_err(|err| {
+            format!("no language server for {}, {} (set language_servers.{}.command)", name, err, name)
+        })?;
+        let result = initialize(&client, &root, options).await?;
+        Ok(Arc::new(LanguageServer {
+            client,
+            capabilities: result.capabilities,
+        }))
+    })
+    .shared()
+}
+
+/**
+ * the language id and server for a file, started if it isn't yet. None for files no
+ * language claims or languages without a command. a failed start stays failed
+ */
+pub fn server_for(path: &Path, first_line: &str, cx: &mut AppContext) -> Option<(String, PendingServer)> {
+    let servers = cx.global::<LanguageServers>();
+    let language = servers.registry.language_for(path, first_line)?.clone();
+    let command = language.command.clone()?;
+    let root = workspace_root(&language, path, &project(cx).root);
+    let key = (language.name.clone(), root.clone());
+    if let Some(server) = servers.servers.get(&key) {
+        return Some((language.name, server.clone()));
+    }
+    let server = start(&language, command, root, cx);
+    cx.global_mut::<LanguageServers>().servers.insert(key, server.clone());
+    Some((language.name, server))
+}
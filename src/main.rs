@@ -1,22 +1,122 @@
 mod text;
 mod files;
 mod lsp;
+mod syntax;
+mod theme;
+mod settings;
+mod project;
 
-use std::{arch::x86_64::_mm_pause, collections::HashMap, env, hash::Hash, thread::sleep, time};
+use std::{arch::x86_64::_mm_pause, collections::HashMap, env, hash::Hash, path::{Path, PathBuf}, thread::sleep, time};
 
-use files::files::load_file;
+use files::files::LoadedFile;
 use gpui::{
     div, prelude::*, px, rgb, size, App, AppContext, Bounds, Context, FocusHandle, FocusableView, KeyBinding, SharedString, TaskLabel, View, ViewContext, WindowBounds, WindowOptions
 };
-use lsp::lsp::run_lsp;
-use text::{text::TextInput, text_input::*};
+use project::{
+    replace::FileEdit,
+    search::{OpenProjectSearch, ProjectQuery, ProjectSearch, ProjectSearchEvent, MAX_RESULTS},
+};
+use settings::settings::{settings, ResetZoom, ZoomIn, ZoomOut};
+use text::{command::*, scroll::*, text::TextInput, text_input::*, wrap::ToggleSoftWrap, word::*, indent::{Indent, Outdent}, brackets::JumpToBracket, search::*, replace::*, undo::{Undo, Redo}};
 use std::error::Error;
 
-use crate::lsp::{decode::Diagnostics, lsp::start_lsp};
+use crate::{text::edit::BufferPoint, theme::theme::theme};
 
 struct File {
     text_input: View<TextInput>, // file lines
     focus_handle: FocusHandle,
+    project_search: Option<View<ProjectSearch>>, // results panel, None until the first :grep
+}
+
+impl File {
+    fn on_project_search_event(&mut self, event: &ProjectSearchEvent, cx: &mut ViewContext<Self>) {
+        match event {
+            ProjectSearchEvent::Search(query) => self.project_search(query.clone(), cx),
+            ProjectSearchEvent::Close => {
+                self.project_search = None;
+                cx.notify();
+            }
+            ProjectSearchEvent::Open { path, point } => self.open_at(path, *point, cx),
+            ProjectSearchEvent::Replace(replacement) => match self.project_search.clone() {
+                Some(panel) => panel.update(cx, |panel, cx| panel.set_replacement(replacement.clone(), cx)),
+                None => self.text_input.update(cx, |input, cx| {
+                    input.status_message = Some("no :grep results to replace".into());
+                    cx.notify();
+                }),
+            },
+            ProjectSearchEvent::Apply => self.apply_project_replace(cx),
+        }
+    }
+
+    /** the open buffer is edited in memory, every other file is rewritten on disk */
+    fn apply_project_replace(&mut self, cx: &mut ViewContext<Self>) {
+        let Some(panel) = self.project_search.clone() else {
+            return;
+        };
+        // results past the cap or still to come were never seen, replacing only some would be a surprise
+        let (searching, limited) = (panel.read(cx).searching, panel.read(cx).limited);
+        let refused = if searching {
+            Some("still searching, :gapply once the results are in".to_string())
+        } else if limited {
+            Some(format!("results stopped at {}, narrow the :grep before :gapply", MAX_RESULTS))
+        } else {
+            None
+        };
+        if let Some(refused) = refused {
+            self.text_input.update(cx, |input, cx| {
+                input.status_message = Some(refused.into());
+                cx.notify();
+            });
+            return;
+        }
+        let open_path = PathBuf::from(&self.text_input.read(cx).open_file);
+        let (open, closed): (Vec<FileEdit>, Vec<FileEdit>) =
+            panel.read(cx).pending_edits().into_iter().partition(|edit| edit.path == open_path);
+        let in_buffer = open.first().map(|edit| self.text_input.update(cx, |input, cx| input.apply_line_edits(edit, cx)));
+        panel.update(cx, |panel, cx| panel.write_edits(closed, in_buffer, cx));
+    }
+
+    fn project_search(&mut self, query: ProjectQuery, cx: &mut ViewContext<Self>) {
+        let input = self.text_input.read(cx);
+        let open_buffer = Some((PathBuf::from(&input.open_file), input.content.clone()));
+        let panel = match self.project_search.clone() {
+            Some(panel) => panel,
+            None => {
+                let panel = cx.new_view(|_| ProjectSearch::new());
+                cx.subscribe(&panel, |this, _, event, cx| this.on_project_search_event(event, cx)).detach();
+                self.project_search = Some(panel.clone());
+                panel
+            }
+        };
+        panel.update(cx, |panel, cx| panel.search(query, open_buffer, cx));
+        cx.notify();
+    }
+
+    /** jumps in the open file, or swaps in a new buffer once the current one is saved */
+    fn open_at(&mut self, path: &Path, point: BufferPoint, cx: &mut ViewContext<Self>) {
+        let current = self.text_input.read(cx);
+        // as paths, a `.` segment in the open file's name still matches
+        if Path::new(&current.open_file) == path {
+            self.text_input.update(cx, |input, cx| input.jump_to_point(input.clamp_point(point), cx));
+        } else if current.dirty {
+            self.text_input.update(cx, |input, cx| {
+                input.status_message = Some("no write since last change, :w first".into());
+                cx.notify();
+            });
+        } else {
+            let text_input = cx.new_view(|cx| {
+                let path = path.to_string_lossy().to_string();
+                let mut input = TextInput::new(cx.focus_handle(), path, LoadedFile::empty(), settings(cx));
+                input.pending_jump = Some(point);
+                input.load_in_background(cx);
+                input
+            });
+            cx.subscribe(&text_input, |this, _, event, cx| this.on_project_search_event(event, cx)).detach();
+            self.text_input = text_input;
+        }
+        cx.focus_view(&self.text_input);
+        cx.notify();
+    }
 }
 
 impl FocusableView for File {
@@ -27,13 +127,16 @@ impl FocusableView for File {
 
 impl Render for File {
     fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let theme = theme(cx);
         div()
-            .bg(rgb(0xaaaaaa))
+            .bg(theme.ui_background)
             .track_focus(&self.focus_handle(cx))
             .flex()
             .flex_col()
             .size_full()
-            .child(self.text_input.clone())
+            .child(div().flex_grow().overflow_hidden().child(self.text_input.clone()))
+            .children(self.project_search.clone())
+            .child(self.text_input.read(cx).render_status_line(&theme))
     }
 }
 
@@ -49,14 +152,19 @@ fn main()  -> Result<(), Box<dyn Error>> {
     }
     let filename = env::current_dir().unwrap().as_os_str().to_str().unwrap().to_owned() + &args[1];
 
-    let lsp = start_lsp(env::current_dir().unwrap().as_os_str().to_str().unwrap().to_owned().clone());
-    println!("waiting");
+    let root = env::current_dir().unwrap();
     let app = App::new();
 
     app.run(|cx: &mut AppContext| {
+        settings::settings::init(cx);
+        theme::theme::init(cx);
+        project::project::init(root, cx);
+        lsp::servers::init(cx);
         let bounds = Bounds::centered(None, size(px(300.0), px(300.0)), cx);
         cx.bind_keys([
             KeyBinding::new("enter", Enter, None),
+            KeyBinding::new("tab", Indent, None),
+            KeyBinding::new("shift-tab", Outdent, None),
             KeyBinding::new("backspace", Backspace, None),
             KeyBinding::new("delete", Delete, None),
             KeyBinding::new("up", Up, None),
@@ -67,6 +175,12 @@ fn main()  -> Result<(), Box<dyn Error>> {
             KeyBinding::new("shift-right", SelectRight, None),
             KeyBinding::new("shift-up", SelectUp, None),
             KeyBinding::new("shift-down", SelectDown, None),
+            KeyBinding::new("ctrl-left", WordLeft, None),
+            KeyBinding::new("ctrl-right", WordRight, None),
+            KeyBinding::new("ctrl-shift-left", SelectWordLeft, None),
+            KeyBinding::new("ctrl-shift-right", SelectWordRight, None),
+            KeyBinding::new("ctrl-backspace", DeleteWordLeft, None),
+            KeyBinding::new("ctrl-delete", DeleteWordRight, None),
             KeyBinding::new("ctrl-a", SelectAll, None),
             KeyBinding::new("ctrl-v", Paste, None),
             KeyBinding::new("ctrl-c", Copy, None),
@@ -75,10 +189,51 @@ fn main()  -> Result<(), Box<dyn Error>> {
             KeyBinding::new("end", End, None),
             KeyBinding::new("ctrl-shift-space", ShowCharacterPalette, None),
             KeyBinding::new("ctrl-s", Save, None),
+            KeyBinding::new("ctrl-z", Undo, None),
+            KeyBinding::new("ctrl-shift-z", Redo, None),
+            KeyBinding::new("ctrl-o u", Undo, None),
+            KeyBinding::new("ctrl-o ctrl-r", Redo, None),
+            KeyBinding::new("ctrl-shift-p", OpenCommandLine, None),
+            KeyBinding::new("ctrl-=", ZoomIn, None),
+            KeyBinding::new("ctrl-+", ZoomIn, None),
+            KeyBinding::new("ctrl--", ZoomOut, None),
+            KeyBinding::new("ctrl-0", ResetZoom, None),
+            KeyBinding::new("ctrl-e", ScrollLineDown, None),
+            KeyBinding::new("ctrl-y", ScrollLineUp, None),
+            KeyBinding::new("ctrl-d", ScrollHalfPageDown, None),
+            KeyBinding::new("ctrl-u", ScrollHalfPageUp, None),
+            // vim's insert mode ctrl-o, there is no normal mode for a bare zz
+            KeyBinding::new("ctrl-o z z", ScrollCursorCenter, None),
+            KeyBinding::new("ctrl-o z t", ScrollCursorTop, None),
+            KeyBinding::new("ctrl-o z b", ScrollCursorBottom, None),
+            KeyBinding::new("alt-z", ToggleSoftWrap, None),
+            KeyBinding::new("ctrl-shift-\\", JumpToBracket, None),
+            KeyBinding::new("ctrl-o %", JumpToBracket, None),
+            KeyBinding::new("ctrl-f", OpenSearch, None),
+            KeyBinding::new("ctrl-o /", OpenSearch, None),
+            KeyBinding::new("ctrl-o ?", OpenSearchBackwards, None),
+            KeyBinding::new("f3", SearchNext, None),
+            KeyBinding::new("shift-f3", SearchPrevious, None),
+            KeyBinding::new("ctrl-o n", SearchNext, None),
+            KeyBinding::new("ctrl-o shift-n", SearchPrevious, None),
+            KeyBinding::new("enter", SearchNext, Some("SearchBar")),
+            KeyBinding::new("shift-enter", SearchPrevious, Some("SearchBar")),
+            KeyBinding::new("escape", CloseSearch, Some("SearchBar")),
+            KeyBinding::new("backspace", SearchBackspace, Some("SearchBar")),
+            KeyBinding::new("alt-r", ToggleSearchRegex, Some("SearchBar")),
+            KeyBinding::new("alt-c", ToggleSearchCase, Some("SearchBar")),
+            KeyBinding::new("ctrl-h", OpenReplace, None),
+            KeyBinding::new("ctrl-shift-f", OpenProjectSearch, None),
+            KeyBinding::new("tab", SwitchSearchField, Some("SearchBar")),
+            KeyBinding::new("ctrl-shift-1", ReplaceNext, Some("SearchBar")),
+            KeyBinding::new("ctrl-alt-enter", ReplaceAll, Some("SearchBar")),
+            KeyBinding::new("alt-p", TogglePreserveCase, Some("SearchBar")),
+            KeyBinding::new("alt-l", ToggleReplaceInSelection, Some("SearchBar")),
+            KeyBinding::new("enter", RunCommand, Some("CommandLine")),
+            KeyBinding::new("escape", CloseCommandLine, Some("CommandLine")),
+            KeyBinding::new("backspace", CommandBackspace, Some("CommandLine")),
         ]);
 
-        let lines = load_file(filename.clone());
-
         let window = cx
             .open_window(
                 WindowOptions {
@@ -87,28 +242,19 @@ fn main()  -> Result<(), Box<dyn Error>> {
                     ..Default::default()
                 },
                 |cx| {
-                    let text_input = cx.new_view(|cx| TextInput {
-                        focus_handle: cx.focus_handle(),
-                        focused_line: 0,
-                        cursor_pos: 0,
-                        open_file: filename,
-                        lines: lines.len(),
-                        content: lines,
-                        placeholder: "".into(),
-                        selected_lines: 0..0,
-                        selected_lines_reversed: false,
-                        selected_range: 0..0,
-                        selection_reversed: false,
-                        marked_range: None,
-                        last_layout: None,
-                        last_bounds: None,
-                        last_cursor_scroll: px(0.0),
-                        is_selecting: false,
-                        diagnostics: HashMap::new(),
+                    let text_input = cx.new_view(|cx| {
+                        let mut input = TextInput::new(cx.focus_handle(), filename, LoadedFile::empty(), settings(cx));
+                        input.load_in_background(cx);
+                        input
                     });
-                    cx.new_view(|cx| File {
-                        text_input,
-                        focus_handle: cx.focus_handle(),
+                    cx.new_view(|cx| {
+                        cx.subscribe(&text_input, |this: &mut File, _, event, cx| this.on_project_search_event(event, cx))
+                            .detach();
+                        File {
+                            text_input,
+                            focus_handle: cx.focus_handle(),
+                            project_search: None,
+                        }
                     })
                 },
             )
@@ -135,60 +281,6 @@ fn main()  -> Result<(), Box<dyn Error>> {
             })
             .unwrap();
 
-        // run lsp
-        cx.spawn(|cx: gpui::AsyncAppContext| async move {
-            let results = cx.background_executor().spawn({
-                run_lsp(lsp)
-            }).await;
-            // signal that lsp ran
-            cx.update(|cx| {
-                window.update(cx, |view, cx| {
-                    cx.update_model(&view.text_input.model, |a, b| {
-                        let results = results.unwrap().1.unwrap();
-                        println!("{:?}", results.get("params").unwrap().get("diagnostics"));
-                        let diagnostics = results.get("params").unwrap().get("diagnostics").unwrap();
-                        
-                        // println!("{}", serde_json::to_string_pretty(diagnostics).unwrap());
-                        // println!("\n\nTWO: \n{}", serde_json::to_string_pretty(&diagnostics.get(1)).unwrap());
-                        a.diagnostics = HashMap::new();
-                        let mut i = 0;
-                        while diagnostics.get(i).is_some() {
-                            let error = diagnostics.get(i).unwrap();
-                            let range = error.get("range").unwrap();
-                            // the get(0) is because relatedInformation is a 1 slot array
-                            let uri = error.get("relatedInformation").unwrap().get(0).unwrap().get("location").unwrap().get("uri").unwrap().as_str().unwrap();
-                            let start = range.get("start").unwrap().get("character").unwrap().as_u64().unwrap() as usize;
-                            let end = range.get("end").unwrap().get("character").unwrap().as_u64().unwrap() as usize;
-                            // TODO line could be range
-                            let line = range.get("start").unwrap().get("line").unwrap().as_u64().unwrap() as usize;
-                            // TODO check file, and multiple error same line
-
-                            if !uri.contains("main.rs") {
-                                i += 1;
-                                continue;
-                            }
-
-                            let diagnostic = Diagnostics {
-                                diagnostic_range: start..end,
-                                is_error: false,
-                                message: "Warning".to_string(),
-                            };
-
-                            if a.diagnostics.contains_key(&line) { // push
-                                a.diagnostics.get_mut(&line).unwrap_or(&mut vec![]).push(diagnostic);
-                            }else { // needs new vector
-                                a.diagnostics.insert(line, vec![diagnostic]);
-                            }
-                            println!("\n\nTWO: \n{}", serde_json::to_string_pretty(error).unwrap());
-                            i += 1;
-                        }
-                    });
-                    cx.notify()
-                }).ok();
-            })
-            .ok();
-            // println!("{:#?}", results);
-        }).detach();
             
     });
     Ok(())
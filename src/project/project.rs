@@ -0,0 +1,53 @@
+use std::path::{Path, PathBuf};
+
+use gpui::{AppContext, Global};
+use ignore::{overrides::OverrideBuilder, WalkBuilder};
+
+/** the directory the editor was started in, lsp and project search work from it */
+pub struct Project {
+    pub root: PathBuf,
+}
+
+impl Global for Project {}
+
+impl Project {
+    /** for showing, absolute paths outside the root stay as they are */
+    pub fn relative<'a>(&self, path: &'a Path) -> &'a Path {
+        path.strip_prefix(&self.root).unwrap_or(path)
+    }
+}
+
+pub fn project(cx: &AppContext) -> &Project {
+    cx.global::<Project>()
+}
+
+pub fn init(root: PathBuf, cx: &mut AppContext) {
+    cx.set_global(Project { root });
+}
+
+/**
+ * every file under `root` that .gitignore (and .ignore, git's global excludes) lets through,
+ * narrowed to `include` when given and without `exclude`. hidden files are skipped like git does
+ */
+pub fn walk_files(root: &Path, include: &[String], exclude: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut overrides = OverrideBuilder::new(root);
+    for glob in include {
+        overrides.add(glob).map_err(|err| err.to_string())?;
+    }
+    for glob in exclude {
+        overrides.add(&format!("!{}", glob)).map_err(|err| err.to_string())?;
+    }
+    let overrides = overrides.build().map_err(|err| err.to_string())?;
+
+    let mut files: Vec<PathBuf> = WalkBuilder::new(root)
+        .overrides(overrides)
+        // not a git repo still has a .gitignore worth following
+        .require_git(false)
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_some_and(|kind| kind.is_file()))
+        .map(|entry| entry.into_path())
+        .collect();
+    files.sort();
+    Ok(files)
+}
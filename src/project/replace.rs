@@ -0,0 +1,364 @@
+use std::path::{Path, PathBuf};
+
+use gpui::{div, prelude::*, Div, MouseButton, MouseDownEvent, SharedString, ViewContext};
+use regex::Regex;
+
+use crate::{
+    files::files::{read_file, save},
+    text::{edit::BufferPoint, text::TextInput},
+    theme::theme::Theme,
+};
+
+use super::{
+    project::project,
+    search::{FileMatches, LineMatch, ProjectQuery, ProjectSearch, ProjectSearchEvent, ResultRow},
+};
+
+/** a line as it was searched and what it becomes */
+#[derive(Clone, Debug)]
+pub struct LineEdit {
+    pub line: usize,
+    pub old: SharedString,
+    pub new: String,
+    pub matches: usize, // ticked ranges replaced in it
+}
+
+#[derive(Clone, Debug)]
+pub struct FileEdit {
+    pub path: PathBuf,
+    pub lines: Vec<LineEdit>,
+}
+
+/** one line of the summary after applying */
+#[derive(Clone, Debug)]
+pub struct AppliedFile {
+    pub path: PathBuf,
+    pub replaced: usize,
+    pub skipped: usize, // lines that changed since the search
+    pub in_buffer: bool, // edited in the open buffer, not saved yet
+    pub error: Option<String>,
+}
+
+/** the line with the ticked ranges replaced, capture groups expand in regex mode */
+fn replace_line(found: &LineMatch, query: &ProjectQuery, regex: &Regex, replacement: &str) -> (String, usize) {
+    let mut new = String::new();
+    let mut copied = 0;
+    let mut count = 0;
+    for (range, included) in found.ranges.iter().zip(&found.included) {
+        if !included {
+            continue;
+        }
+        // the same match again, for its groups
+        let Some(captures) = regex.captures_at(&found.text, range.start).filter(|captures| {
+            captures.get(0).is_some_and(|whole| whole.range() == *range)
+        }) else {
+            continue;
+        };
+        new.push_str(&found.text[copied..range.start]);
+        if query.regex {
+            captures.expand(replacement, &mut new);
+        } else {
+            new.push_str(replacement);
+        }
+        copied = range.end;
+        count += 1;
+    }
+    new.push_str(&found.text[copied..]);
+    (new, count)
+}
+
+/** rewrites lines that still read as they did when searched, the file is replaced atomically */
+fn write_file_edit(edit: &FileEdit) -> AppliedFile {
+    let mut applied = AppliedFile {
+        path: edit.path.clone(),
+        replaced: 0,
+        skipped: 0,
+        in_buffer: false,
+        error: None,
+    };
+    let loaded = match read_file(&edit.path) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            applied.error = Some(err.to_string());
+            return applied;
+        }
+    };
+    if let Some(err) = loaded.error {
+        // wouldn't save back byte for byte, same as a read only buffer
+        applied.error = Some(err);
+        return applied;
+    }
+    let mut lines = loaded.lines;
+    for line in &edit.lines {
+        if lines.get(line.line) != Some(&line.old) {
+            applied.skipped += 1;
+            continue;
+        }
+        lines[line.line] = line.new.clone().into();
+        applied.replaced += line.matches;
+    }
+    if applied.replaced > 0 {
+        if let Err(err) = save(edit.path.to_string_lossy().to_string(), lines, loaded.format, false) {
+            applied.error = Some(err.to_string());
+            applied.replaced = 0;
+        }
+    }
+    applied
+}
+
+impl ProjectSearch {
+    pub fn set_replacement(&mut self, replacement: String, cx: &mut ViewContext<Self>) {
+        self.replacement = Some(replacement);
+        self.applied.clear();
+        self.update_edits();
+        cx.notify();
+    }
+
+    /** the ticked matches of one file as line edits, None when nothing in it is ticked */
+    fn file_edit(&self, file: &FileMatches) -> Option<FileEdit> {
+        let (Some(regex), Some(replacement)) = (self.regex.as_ref(), self.replacement.as_ref()) else {
+            return None;
+        };
+        if !file.included {
+            return None;
+        }
+        let lines: Vec<LineEdit> = file
+            .lines
+            .iter()
+            .filter_map(|found| {
+                let (new, matches) = replace_line(found, &self.query, regex, replacement);
+                (matches > 0).then(|| LineEdit {
+                    line: found.line,
+                    old: found.text.clone(),
+                    new,
+                    matches,
+                })
+            })
+            .collect();
+        (!lines.is_empty()).then(|| FileEdit {
+            path: file.path.clone(),
+            lines,
+        })
+    }
+
+    /** runs the replacement over every result, rendering only reads `edits` */
+    pub fn update_edits(&mut self) {
+        self.edits = self.results.iter().filter_map(|file| self.file_edit(file)).collect();
+    }
+
+    /** after a tick changed in one file */
+    fn update_file_edit(&mut self, path: &Path) {
+        let edit = self.results.iter().find(|file| file.path == path).and_then(|file| self.file_edit(file));
+        let ix = self.edits.binary_search_by(|edit| edit.path.as_path().cmp(path));
+        match (ix, edit) {
+            (Ok(ix), Some(edit)) => self.edits[ix] = edit,
+            (Ok(ix), None) => {
+                self.edits.remove(ix);
+            }
+            (Err(ix), Some(edit)) => self.edits.insert(ix, edit),
+            (Err(_), None) => {}
+        }
+    }
+
+    /** every ticked match as line edits, grouped by file */
+    pub fn pending_edits(&self) -> Vec<FileEdit> {
+        self.edits.clone()
+    }
+
+    /**
+     * writes the files that aren't open off the ui thread, `in_buffer` is what the editor
+     * already applied to the open one. the summary replaces the results
+     */
+    pub fn write_edits(&mut self, edits: Vec<FileEdit>, in_buffer: Option<AppliedFile>, cx: &mut ViewContext<Self>) {
+        // batches still coming in would rebuild edits under the summary
+        self.search_task = None;
+        self.searching = false;
+        self.results.clear();
+        self.edits.clear();
+        self.applied = in_buffer.into_iter().collect();
+        self.write_task = Some(cx.spawn(|this, mut cx| async move {
+            let written = cx
+                .background_executor()
+                .spawn(async move { edits.iter().map(write_file_edit).collect::<Vec<_>>() })
+                .await;
+            let _ = this.update(&mut cx, |this, cx| {
+                this.applied.extend(written);
+                this.applied.sort_by(|a, b| a.path.cmp(&b.path));
+                this.replacement = None;
+                cx.notify();
+            });
+        }));
+        cx.notify();
+    }
+
+    fn toggle_file(&mut self, path: &Path, cx: &mut ViewContext<Self>) {
+        if let Some(file) = self.results.iter_mut().find(|file| file.path == path) {
+            file.included = !file.included;
+        }
+        self.update_file_edit(path);
+        cx.notify();
+    }
+
+    fn toggle_match(&mut self, path: &Path, line: usize, range: usize, cx: &mut ViewContext<Self>) {
+        let file = self.results.iter_mut().find(|file| file.path == path);
+        if let Some(found) = file.and_then(|file| file.lines.iter_mut().find(|found| found.line == line)) {
+            found.included[range] = !found.included[range];
+        }
+        self.update_file_edit(path);
+        cx.notify();
+    }
+
+    pub fn render_apply_button(&self, theme: &Theme, cx: &mut ViewContext<Self>) -> Option<Div> {
+        // applying part of the matches would look like all of them were replaced
+        if self.replacement.is_none() || self.limited || self.searching {
+            return None;
+        }
+        let count: usize = self.edits.iter().flat_map(|file| &file.lines).map(|line| line.matches).sum();
+        Some(
+            div()
+                .px_1()
+                .bg(theme.selection)
+                .cursor_pointer()
+                .child(format!("replace {}", count))
+                .on_mouse_down(MouseButton::Left, cx.listener(|_, _: &MouseDownEvent, cx| cx.emit(ProjectSearchEvent::Apply))),
+        )
+    }
+
+    fn checkbox(on: bool) -> &'static str {
+        if on {
+            "[x]"
+        } else {
+            "[ ]"
+        }
+    }
+
+    /** what the ticked matches of a result line turn it into */
+    pub fn new_line(&self, file: usize, found: usize) -> Option<&str> {
+        let (path, line) = (&self.results[file].path, self.results[file].lines[found].line);
+        let edit = &self.edits[self.edits.binary_search_by(|edit| edit.path.cmp(path)).ok()?];
+        // lines come in the same order as the results
+        let ix = edit.lines.binary_search_by_key(&line, |line| line.line).ok()?;
+        Some(&edit.lines[ix].new)
+    }
+
+    /** the diff shows each match line as - old / + new, with a box per match and one for the file */
+    pub fn render_diff_file(&self, file: usize, theme: &Theme, cx: &mut ViewContext<Self>) -> Div {
+        let file = &self.results[file];
+        let shown_path = project(cx).relative(&file.path).display().to_string();
+        let path = file.path.clone();
+        div()
+            .flex()
+            .flex_row()
+            .gap_1()
+            .text_color(theme.gutter_active_foreground)
+            .child(
+                div()
+                    .cursor_pointer()
+                    .child(Self::checkbox(file.included))
+                    .on_mouse_down(MouseButton::Left, cx.listener(move |this, _: &MouseDownEvent, cx| this.toggle_file(&path, cx))),
+            )
+            .child(shown_path)
+    }
+
+    pub fn render_diff_match(&self, file: usize, found: usize, theme: &Theme, cx: &mut ViewContext<Self>) -> Div {
+        let (file, found) = (&self.results[file], &self.results[file].lines[found]);
+        let mut boxes = div().flex().flex_row().gap_1().flex_none();
+        for range in 0..found.ranges.len() {
+            let (path, line) = (file.path.clone(), found.line);
+            boxes = boxes.child(
+                div()
+                    .cursor_pointer()
+                    .child(Self::checkbox(file.included && found.included[range]))
+                    .on_mouse_down(
+                        MouseButton::Left,
+                        cx.listener(move |this, _: &MouseDownEvent, cx| this.toggle_match(&path, line, range, cx)),
+                    ),
+            );
+        }
+        div()
+            .flex()
+            .flex_row()
+            .gap_1()
+            .child(boxes)
+            .child(Self::render_line(found.line, &found.text, &found.ranges, theme).bg(theme.error.opacity(0.1)))
+    }
+
+    /** the + line, blanks where the boxes are above it so both texts line up */
+    pub fn render_diff_replaced(&self, file: usize, found: usize, theme: &Theme) -> Div {
+        let line = &self.results[file].lines[found];
+        let new = self.new_line(file, found).unwrap_or_default();
+        let blanks = (0..line.ranges.len()).map(|_| div().child("   "));
+        div()
+            .flex()
+            .flex_row()
+            .gap_1()
+            .child(div().flex().flex_row().gap_1().flex_none().children(blanks))
+            .child(Self::render_line(line.line, new, &[], theme).bg(theme.info.opacity(0.1)))
+    }
+
+    pub fn render_applied_row(&self, row: ResultRow, theme: &Theme, cx: &mut ViewContext<Self>) -> Div {
+        let file = match row {
+            ResultRow::Applied(ix) => &self.applied[ix],
+            ResultRow::AppliedSummary => {
+                let replaced: usize = self.applied.iter().map(|file| file.replaced).sum();
+                let files = self.applied.iter().filter(|file| file.replaced > 0).count();
+                return div().child(format!("replaced {} matches in {} files", replaced, files));
+            }
+            _ => return div().child("writing files..."),
+        };
+        let mut line = format!("{}  {} replaced", project(cx).relative(&file.path).display(), file.replaced);
+        if file.skipped > 0 {
+            line += &format!(", {} lines skipped, changed since the search", file.skipped);
+        }
+        if file.in_buffer {
+            line += ", in the open buffer, not saved";
+        }
+        let row = div().flex().flex_row().whitespace_nowrap().child(line);
+        match &file.error {
+            Some(err) => row.text_color(theme.error).child(format!("  {}", err)),
+            None => row,
+        }
+    }
+}
+
+impl TextInput {
+    /**
+     * project replace in the open buffer, all lines in one edit. lines that no longer read
+     * like they did when searched are skipped
+     */
+    pub fn apply_line_edits(&mut self, edit: &FileEdit, cx: &mut ViewContext<Self>) -> AppliedFile {
+        let mut applied = AppliedFile {
+            path: edit.path.clone(),
+            replaced: 0,
+            skipped: 0,
+            in_buffer: true,
+            error: None,
+        };
+        if self.refuse_edit(cx) {
+            applied.error = Some("buffer is read only".to_string());
+            return applied;
+        }
+        let (current, stale): (Vec<&LineEdit>, Vec<&LineEdit>) =
+            edit.lines.iter().partition(|line| self.content.get(line.line) == Some(&line.old));
+        applied.skipped = stale.len();
+        let (Some(first), Some(last)) = (current.first(), current.last()) else {
+            return applied;
+        };
+        let (first, last) = (first.line, last.line);
+
+        let mut lines: Vec<String> = self.content[first..=last].iter().map(|line| line.to_string()).collect();
+        for line in &current {
+            lines[line.line - first] = line.new.clone();
+            applied.replaced += line.matches;
+        }
+        self.dirty = true;
+        let end = BufferPoint::new(last, self.content[last].len());
+        // undone in one step like replace all
+        self.begin_transaction();
+        self.replace_range(BufferPoint::new(first, 0), end, &lines.join("\n"));
+        self.end_transaction();
+        let cursor = self.clamp_point(self.cursor_point());
+        self.jump_to_point(cursor, cx);
+        applied
+    }
+}
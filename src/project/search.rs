@@ -0,0 +1,519 @@
+use std::{
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use futures::{channel::mpsc, StreamExt};
+use gpui::{
+    actions, div, prelude::*, relative, uniform_list, Div, EventEmitter, MouseButton, MouseDownEvent, SharedString,
+    Task, ViewContext,
+};
+use regex::Regex;
+
+use crate::{
+    files::{encoding::decode, format::split_lines},
+    settings::settings::settings,
+    text::{
+        edit::BufferPoint,
+        search::{build_regex, CaseMode},
+        text::TextInput,
+    },
+    theme::theme::{theme, Theme},
+};
+
+use super::{
+    project::{project, walk_files},
+    replace::{AppliedFile, FileEdit},
+};
+
+actions!(project_search, [OpenProjectSearch]);
+
+const CONTEXT_LINES: usize = 2; // shown above and below each matching line
+pub const MAX_RESULTS: usize = 10_000; // matching lines, the search stops once it has this many
+const GREP_USAGE: &str = "usage: grep [-F] [-i|-s] [-w] [--include=glob] [--exclude=glob] pattern";
+
+/** what `:grep` was asked for, regex unless -F */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProjectQuery {
+    pub pattern: String,
+    pub regex: bool,
+    pub case: CaseMode,
+    pub whole_word: bool,
+    pub include: Vec<String>, // globs, only these files when given
+    pub exclude: Vec<String>,
+}
+
+impl ProjectQuery {
+    /** `-F` fixed string, `-i`/`-s` case, `-w` whole word, the rest of the line is the pattern */
+    pub fn parse(args: &str) -> Result<Self, String> {
+        let mut query = ProjectQuery {
+            regex: true,
+            ..Default::default()
+        };
+        let mut rest = args.trim_start();
+        loop {
+            let (word, after) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            match word {
+                "-F" => query.regex = false,
+                "-i" => query.case = CaseMode::Insensitive,
+                "-s" => query.case = CaseMode::Sensitive,
+                "-w" => query.whole_word = true,
+                "--" => {
+                    rest = after.trim_start();
+                    break;
+                }
+                _ if word.starts_with("--include=") => query.include.push(word["--include=".len()..].to_string()),
+                _ if word.starts_with("--exclude=") => query.exclude.push(word["--exclude=".len()..].to_string()),
+                _ => break,
+            }
+            rest = after.trim_start();
+        }
+        if rest.is_empty() {
+            return Err(GREP_USAGE.to_string());
+        }
+        query.pattern = rest.to_string();
+        Ok(query)
+    }
+
+    pub fn build_regex(&self) -> Result<Regex, String> {
+        if !self.whole_word {
+            return build_regex(&self.pattern, self.regex, self.case);
+        }
+        let pattern = if self.regex { self.pattern.clone() } else { regex::escape(&self.pattern) };
+        build_regex(&format!(r"\b(?:{})\b", pattern), true, self.case)
+    }
+}
+
+/** a line with at least one match, and the lines around it not already shown for another */
+#[derive(Clone, Debug)]
+pub struct LineMatch {
+    pub line: usize,
+    pub text: SharedString,
+    pub ranges: Vec<Range<usize>>, // bytes into text
+    pub included: Vec<bool>, // per range, unticked ones are left alone by replace
+    pub before: Vec<(usize, SharedString)>,
+    pub after: Vec<(usize, SharedString)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FileMatches {
+    pub path: PathBuf,
+    pub lines: Vec<LineMatch>,
+    pub included: bool,
+}
+
+/** a line of the results panel, indices into results or applied. all rows are one line high */
+#[derive(Clone, Copy, Debug)]
+pub enum ResultRow {
+    File(usize),
+    Context { file: usize, found: usize, after: bool, ix: usize }, // into before or after
+    Match { file: usize, found: usize },
+    Replaced { file: usize, found: usize }, // the new text under a match, in the diff
+    Gap, // after each file
+    AppliedSummary,
+    Applied(usize),
+    Writing,
+}
+
+pub enum ProjectSearchEvent {
+    Search(ProjectQuery),
+    Close,
+    Open { path: PathBuf, point: BufferPoint },
+    Replace(String), // preview replacing every match with this
+    Apply,
+}
+
+impl EventEmitter<ProjectSearchEvent> for TextInput {}
+
+/** decoded lines like a buffer would have them, None for binary files and ones we can't read */
+pub fn read_lines(path: &Path) -> Option<Vec<SharedString>> {
+    let bytes = fs::read(path).ok()?;
+    let decoded = decode(&bytes);
+    // NULs survive decoding as text only in utf-16
+    if decoded.text.contains('\0') {
+        return None;
+    }
+    let (lines, _) = split_lines(&decoded.text);
+    Some(lines.into_iter().map(|line| SharedString::from(line.to_string())).collect())
+}
+
+/** matching lines with their context, context is never repeated between neighbours */
+pub fn search_lines(lines: &[SharedString], regex: &Regex) -> Vec<LineMatch> {
+    let hits: Vec<(usize, Vec<Range<usize>>)> = lines
+        .iter()
+        .enumerate()
+        .filter_map(|(line, text)| {
+            let ranges: Vec<Range<usize>> =
+                regex.find_iter(text).filter(|found| !found.is_empty()).map(|found| found.range()).collect();
+            (!ranges.is_empty()).then_some((line, ranges))
+        })
+        .collect();
+
+    let context = |range: Range<usize>| range.map(|line| (line, lines[line].clone())).collect();
+    let mut shown_until = 0; // first line not shown yet
+    hits.iter()
+        .enumerate()
+        .map(|(ix, (line, ranges))| {
+            let before_start = line.saturating_sub(CONTEXT_LINES).max(shown_until);
+            let next_hit = hits.get(ix + 1).map(|(next, _)| *next).unwrap_or(lines.len());
+            let after_end = (line + 1 + CONTEXT_LINES).min(next_hit).min(lines.len());
+            shown_until = after_end;
+            LineMatch {
+                line: *line,
+                text: lines[*line].clone(),
+                ranges: ranges.clone(),
+                included: vec![true; ranges.len()],
+                before: context(before_start..*line),
+                after: context(line + 1..after_end),
+            }
+        })
+        .collect()
+}
+
+/** the results panel under the editor, filled in while the search runs */
+pub struct ProjectSearch {
+    pub query: ProjectQuery,
+    pub results: Vec<FileMatches>, // by path, files come in whenever a worker finishes one
+    pub searching: bool,
+    pub limited: bool, // stopped at MAX_RESULTS
+    pub error: Option<String>,
+    pub search_task: Option<Task<()>>,
+    pub regex: Option<Regex>, // of the last search, replace needs its capture groups
+    pub replacement: Option<String>, // Some shows the results as a diff
+    pub edits: Vec<FileEdit>, // the ticked matches replaced, by path, redone when a tick or the replacement changes
+    pub applied: Vec<AppliedFile>, // what the last replace did, shown instead of results
+    pub write_task: Option<Task<()>>,
+    pub rows: Vec<ResultRow>, // from result_rows, each render
+}
+
+impl EventEmitter<ProjectSearchEvent> for ProjectSearch {}
+
+impl ProjectSearch {
+    pub fn new() -> Self {
+        ProjectSearch {
+            query: ProjectQuery::default(),
+            results: vec![],
+            searching: false,
+            limited: false,
+            error: None,
+            search_task: None,
+            regex: None,
+            replacement: None,
+            edits: vec![],
+            applied: vec![],
+            write_task: None,
+            rows: vec![],
+        }
+    }
+
+    /**
+     * walks the project and searches the files on the background executor, a worker per core.
+     * `open_buffer` is searched instead of its file on disk, it may have unsaved edits
+     */
+    pub fn search(
+        &mut self,
+        query: ProjectQuery,
+        open_buffer: Option<(PathBuf, Vec<SharedString>)>,
+        cx: &mut ViewContext<Self>,
+    ) {
+        self.results.clear();
+        self.limited = false;
+        self.error = None;
+        self.query = query.clone();
+        self.search_task = None;
+        self.regex = None;
+        self.replacement = None;
+        self.edits.clear();
+        self.applied.clear();
+        let regex = match query.build_regex() {
+            Ok(regex) => regex,
+            Err(err) => {
+                self.error = Some(err);
+                self.searching = false;
+                cx.notify();
+                return;
+            }
+        };
+        self.searching = true;
+        self.regex = Some(regex.clone());
+        let root = project(cx).root.clone();
+
+        self.search_task = Some(cx.spawn(|this, mut cx| async move {
+            let executor = cx.background_executor().clone();
+            let (include, exclude) = (query.include.clone(), query.exclude.clone());
+            let files = match executor.spawn(async move { walk_files(&root, &include, &exclude) }).await {
+                Ok(files) => Arc::new(files),
+                Err(err) => {
+                    let _ = this.update(&mut cx, |this, cx| {
+                        this.error = Some(err);
+                        this.searching = false;
+                        cx.notify();
+                    });
+                    return;
+                }
+            };
+
+            let (sender, mut receiver) = mpsc::unbounded();
+            let next_file = Arc::new(AtomicUsize::new(0));
+            let found_lines = Arc::new(AtomicUsize::new(0));
+            let open_buffer = Arc::new(open_buffer);
+            let workers = std::thread::available_parallelism().map(|count| count.get()).unwrap_or(4);
+            for _ in 0..workers {
+                let (files, next_file, found_lines) = (files.clone(), next_file.clone(), found_lines.clone());
+                let (open_buffer, regex, sender) = (open_buffer.clone(), regex.clone(), sender.clone());
+                executor
+                    .spawn(async move {
+                        // a newer search dropped the receiver
+                        while !sender.is_closed() && found_lines.load(Ordering::Relaxed) < MAX_RESULTS {
+                            let Some(path) = files.get(next_file.fetch_add(1, Ordering::Relaxed)) else {
+                                break;
+                            };
+                            let lines = match open_buffer.as_ref() {
+                                Some((open, lines)) if open == path => Some(lines.clone()),
+                                _ => read_lines(path),
+                            };
+                            let Some(lines) = lines else {
+                                continue;
+                            };
+                            let matches = search_lines(&lines, &regex);
+                            if matches.is_empty() {
+                                continue;
+                            }
+                            found_lines.fetch_add(matches.len(), Ordering::Relaxed);
+                            let _ = sender.unbounded_send(FileMatches {
+                                path: path.clone(),
+                                lines: matches,
+                                included: true,
+                            });
+                        }
+                    })
+                    .detach();
+            }
+            drop(sender);
+
+            while let Some(first) = receiver.next().await {
+                // everything that came in meanwhile goes in with one redraw
+                let mut batch = vec![first];
+                while let Ok(Some(more)) = receiver.try_next() {
+                    batch.push(more);
+                }
+                let updated = this.update(&mut cx, |this, cx| {
+                    for file in batch {
+                        let ix = this.results.partition_point(|result| result.path < file.path);
+                        this.results.insert(ix, file);
+                    }
+                    // a :greplace before the search finished
+                    if this.replacement.is_some() {
+                        this.update_edits();
+                    }
+                    cx.notify();
+                });
+                if updated.is_err() {
+                    return;
+                }
+            }
+            let _ = this.update(&mut cx, |this, cx| {
+                this.searching = false;
+                this.limited = found_lines.load(Ordering::Relaxed) >= MAX_RESULTS;
+                cx.notify();
+            });
+        }));
+        cx.notify();
+    }
+
+    /** clicking an option searches again, through the editor so the open buffer is fresh */
+    fn toggle(&self, label: &'static str, on: bool, change: fn(&mut ProjectQuery), theme: &Theme, cx: &mut ViewContext<Self>) -> Div {
+        let query = self.query.clone();
+        div()
+            .px_1()
+            .when(on, |this| this.bg(theme.selection))
+            .child(label)
+            .on_mouse_down(
+                MouseButton::Left,
+                cx.listener(move |_, _: &MouseDownEvent, cx| {
+                    let mut query = query.clone();
+                    change(&mut query);
+                    cx.emit(ProjectSearchEvent::Search(query));
+                }),
+            )
+    }
+
+    fn summary(&self) -> String {
+        if let Some(err) = &self.error {
+            return err.clone();
+        }
+        let lines: usize = self.results.iter().map(|file| file.lines.len()).sum();
+        let mut summary = format!("{} lines in {} files", lines, self.results.len());
+        if self.limited {
+            summary += &format!(", stopped at {}", MAX_RESULTS);
+        }
+        if self.searching {
+            summary += ", searching...";
+        }
+        summary
+    }
+
+    pub fn render_line(line: usize, text: &str, ranges: &[Range<usize>], theme: &Theme) -> Div {
+        let mut pieces = div().flex().flex_row();
+        let mut shown = 0;
+        for range in ranges {
+            pieces = pieces
+                .child(text[shown..range.start].to_string())
+                .child(div().bg(theme.search_match()).child(text[range.clone()].to_string()));
+            shown = range.end;
+        }
+        pieces = pieces.child(text[shown..].to_string());
+
+        div()
+            .flex()
+            .flex_row()
+            .whitespace_nowrap()
+            // padded to the same width in the monospace font, whatever its size
+            .child(div().flex_none().text_color(theme.gutter_foreground).child(format!("{:>5} ", line + 1)))
+            .child(pieces)
+    }
+
+    /** what the panel shows, one entry per line of it. the text stays in results */
+    fn result_rows(&self) -> Vec<ResultRow> {
+        if !self.applied.is_empty() {
+            let mut rows = vec![ResultRow::AppliedSummary, ResultRow::Gap];
+            rows.extend((0..self.applied.len()).map(ResultRow::Applied));
+            // cleared once the files are written
+            if self.replacement.is_some() {
+                rows.push(ResultRow::Writing);
+            }
+            return rows;
+        }
+        let mut rows = vec![];
+        for (file, matches) in self.results.iter().enumerate() {
+            rows.push(ResultRow::File(file));
+            for (found, line) in matches.lines.iter().enumerate() {
+                if self.replacement.is_some() {
+                    rows.push(ResultRow::Match { file, found });
+                    if self.new_line(file, found).is_some() {
+                        rows.push(ResultRow::Replaced { file, found });
+                    }
+                    continue;
+                }
+                rows.extend((0..line.before.len()).map(|ix| ResultRow::Context { file, found, after: false, ix }));
+                rows.push(ResultRow::Match { file, found });
+                rows.extend((0..line.after.len()).map(|ix| ResultRow::Context { file, found, after: true, ix }));
+            }
+            rows.push(ResultRow::Gap);
+        }
+        rows
+    }
+
+    fn render_row(&self, row: ResultRow, theme: &Theme, cx: &mut ViewContext<Self>) -> Div {
+        let diff = self.replacement.is_some();
+        match row {
+            ResultRow::File(file) if diff => self.render_diff_file(file, theme, cx),
+            ResultRow::File(file) => {
+                let file = &self.results[file];
+                let shown_path = project(cx).relative(&file.path).display().to_string();
+                div()
+                    .text_color(theme.gutter_active_foreground)
+                    .child(format!("{}  ({})", shown_path, file.lines.len()))
+            }
+            ResultRow::Context { file, found, after, ix } => {
+                let found = &self.results[file].lines[found];
+                let (line, text) = if after { &found.after[ix] } else { &found.before[ix] };
+                Self::render_line(*line, text, &[], theme).text_color(theme.foreground.opacity(0.5))
+            }
+            ResultRow::Match { file, found } if diff => self.render_diff_match(file, found, theme, cx),
+            ResultRow::Match { file, found } => {
+                let (path, found) = (self.results[file].path.clone(), &self.results[file].lines[found]);
+                let point = BufferPoint::new(found.line, found.ranges[0].start);
+                Self::render_line(found.line, &found.text, &found.ranges, theme)
+                    .cursor_pointer()
+                    .on_mouse_down(
+                        MouseButton::Left,
+                        cx.listener(move |_, _: &MouseDownEvent, cx| {
+                            cx.emit(ProjectSearchEvent::Open {
+                                path: path.clone(),
+                                point,
+                            });
+                        }),
+                    )
+            }
+            ResultRow::Replaced { file, found } => self.render_diff_replaced(file, found, theme),
+            ResultRow::Gap => div(),
+            ResultRow::AppliedSummary | ResultRow::Applied(_) | ResultRow::Writing => self.render_applied_row(row, theme, cx),
+        }
+    }
+}
+
+impl Render for ProjectSearch {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let theme = theme(cx);
+        let settings = settings(cx).clone();
+
+        let header = div()
+            .flex()
+            .flex_row()
+            .gap_2()
+            .px_2()
+            .bg(theme.status_bar_background)
+            .text_color(theme.status_bar_foreground)
+            .child(format!("grep {}", self.query.pattern))
+            .child(self.toggle(".*", self.query.regex, |query| query.regex = !query.regex, &theme, cx))
+            .child(self.toggle(
+                "Aa",
+                self.query.case == CaseMode::Sensitive,
+                |query| {
+                    query.case = match query.case {
+                        CaseMode::Sensitive => CaseMode::Smart,
+                        _ => CaseMode::Sensitive,
+                    }
+                },
+                &theme,
+                cx,
+            ))
+            .child(self.toggle("\\b", self.query.whole_word, |query| query.whole_word = !query.whole_word, &theme, cx))
+            .child(div().flex_grow().child(self.summary()))
+            .children(self.render_apply_button(&theme, cx))
+            .child(
+                div()
+                    .px_1()
+                    .child("x")
+                    .on_mouse_down(MouseButton::Left, cx.listener(|_, _: &MouseDownEvent, cx| cx.emit(ProjectSearchEvent::Close))),
+            );
+
+        self.rows = self.result_rows();
+
+        div()
+            .flex()
+            .flex_col()
+            .flex_none()
+            .w_full()
+            .h(relative(0.4))
+            .bg(theme.background)
+            .text_color(theme.foreground)
+            .font_family(settings.font_family())
+            .text_size(settings.metrics().font_size)
+            .child(header)
+            .child(
+                // up to MAX_RESULTS matches with their context, only the visible rows are built
+                uniform_list(cx.view().clone(), "project-search-results", self.rows.len(), {
+                    let theme = theme.clone();
+                    move |this, range, cx| range.map(|ix| this.render_row(this.rows[ix], &theme, cx)).collect()
+                })
+                .flex_grow()
+                .px_2(),
+            )
+    }
+}
+
+impl TextInput {
+    /** ctrl-shift-f, the query is typed as a `:grep` */
+    pub fn open_project_search(&mut self, _: &OpenProjectSearch, cx: &mut ViewContext<Self>) {
+        self.search_open = false;
+        self.command_line = Some("grep ".to_string());
+        cx.notify();
+    }
+}
@@ -0,0 +1,244 @@
+use std::{collections::HashMap, fs, path::{Path, PathBuf}};
+
+use gpui::{actions, font, px, AppContext, Font, FontFeatures, FontWeight, Global, Pixels, SharedString};
+use serde::Deserialize;
+use serde_json::Value;
+
+actions!(settings, [ZoomIn, ZoomOut, ResetZoom]);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineNumbers {
+    Off,
+    #[default]
+    Absolute,
+    Relative,
+    Hybrid, // absolute on the cursor line, relative elsewhere
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SoftWrap {
+    #[default]
+    Off,
+    Viewport,
+    Column, // at wrap_column, or the viewport if that is narrower
+}
+
+/** what ctrl-left/right and ctrl-backspace treat as a word */
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct WordSettings {
+    pub underscore_is_word: bool, // foo_bar is one word
+    pub punctuation_stops: bool, // runs of punctuation are words, otherwise skipped like spaces
+    pub subwords: bool, // stop inside camelCase and snake_case
+}
+
+impl Default for WordSettings {
+    fn default() -> Self {
+        WordSettings {
+            underscore_is_word: true,
+            punctuation_stops: true,
+            subwords: false,
+        }
+    }
+}
+
+/**
+ * one entry of `language_servers`, by language id. set fields replace the built in ones,
+ * unknown ids add a language: {"python": {"command": "pylsp", "args": []}, "go": {"enabled": false}}
+ */
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LanguageServerSettings {
+    pub enabled: Option<bool>,
+    pub command: Option<String>,
+    pub args: Option<Vec<String>>,
+    pub extensions: Option<Vec<String>>,
+    pub filenames: Option<Vec<String>>,
+    pub shebangs: Option<Vec<String>>,
+    pub root_markers: Option<Vec<String>>,
+    pub initialization_options: Option<Value>,
+}
+
+/** ~/.config/nvim2/settings.json, everything optional */
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub font_family: String,
+    pub font_size: f32,
+    pub font_weight: f32,
+    pub ligatures: bool,
+    pub line_height: f32, // multiple of the font size
+    pub theme: Option<String>,
+    pub line_numbers: LineNumbers,
+    pub scrolloff: usize, // lines kept between the cursor and the top/bottom edge
+    pub smooth_scroll: bool,
+    #[serde(alias = "tab_width")] // what the setting was first called
+    pub tab_size: usize, // columns a tab takes
+    pub indent_width: usize,
+    pub insert_spaces: bool,
+    pub detect_indent: bool, // files keep the style they already use
+    pub words: WordSettings,
+    pub soft_wrap: SoftWrap,
+    pub wrap_column: usize,
+    pub soft_wrap_filetypes: HashMap<String, SoftWrap>, // by extension, {"md": "viewport"}
+    pub rainbow_brackets: bool, // brackets colored by nesting depth
+    pub backup: bool, // saving keeps the previous version as file~
+    pub language_servers: HashMap<String, LanguageServerSettings>,
+    #[serde(skip)]
+    pub zoom: f32, // ctrl-+/ctrl--, added to font_size, not saved
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            font_family: "Zed Plex Mono".to_string(),
+            font_size: 24.,
+            font_weight: FontWeight::NORMAL.0,
+            ligatures: true,
+            line_height: 1.25,
+            theme: None,
+            line_numbers: LineNumbers::default(),
+            scrolloff: 3,
+            smooth_scroll: false,
+            tab_size: 4,
+            indent_width: 4,
+            insert_spaces: true,
+            detect_indent: true,
+            words: WordSettings::default(),
+            soft_wrap: SoftWrap::Off,
+            wrap_column: 80,
+            soft_wrap_filetypes: HashMap::from([
+                ("md".to_string(), SoftWrap::Viewport),
+                ("txt".to_string(), SoftWrap::Viewport),
+            ]),
+            rainbow_brackets: false,
+            backup: false,
+            language_servers: HashMap::new(),
+            zoom: 0.,
+        }
+    }
+}
+
+impl Global for Settings {}
+
+const MIN_FONT_SIZE: f32 = 6.;
+const MAX_FONT_SIZE: f32 = 96.;
+
+/**
+ * sizes every pixel <-> buffer line conversion goes through, so zooming or changing the
+ * font can't leave one place assuming the old line height
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct LineMetrics {
+    pub font_size: Pixels,
+    pub line_height: Pixels,
+}
+
+impl LineMetrics {
+    /** lines, fractional, that `y` spans */
+    pub fn lines_for_y(&self, y: Pixels) -> f32 {
+        y / self.line_height
+    }
+
+    pub fn y_for_lines(&self, lines: f32) -> Pixels {
+        self.line_height * lines
+    }
+
+    /** whole lines that fit in `height` */
+    pub fn lines_in(&self, height: Pixels) -> usize {
+        (height / self.line_height).floor().max(1.) as usize
+    }
+}
+
+impl Settings {
+    pub fn soft_wrap_for(&self, path: &str) -> SoftWrap {
+        Path::new(path)
+            .extension()
+            .and_then(|extension| self.soft_wrap_filetypes.get(extension.to_str()?))
+            .copied()
+            .unwrap_or(self.soft_wrap)
+    }
+
+    pub fn metrics(&self) -> LineMetrics {
+        let font_size = (self.font_size + self.zoom).clamp(MIN_FONT_SIZE, MAX_FONT_SIZE);
+        LineMetrics {
+            font_size: px(font_size),
+            line_height: px((font_size * self.line_height).round()),
+        }
+    }
+
+    pub fn font_family(&self) -> SharedString {
+        self.font_family.clone().into()
+    }
+
+    pub fn font_weight(&self) -> FontWeight {
+        FontWeight(self.font_weight)
+    }
+
+    pub fn font_features(&self) -> FontFeatures {
+        if self.ligatures {
+            FontFeatures::default()
+        } else {
+            FontFeatures::disable_ligatures()
+        }
+    }
+
+    /** the editor font, for shaping outside the element tree's text style */
+    pub fn font(&self) -> Font {
+        let mut font = font(self.font_family());
+        font.weight = self.font_weight();
+        font.features = self.font_features();
+        font
+    }
+
+    fn load() -> Self {
+        let Some(path) = settings_path() else {
+            return Settings::default();
+        };
+        let Ok(source) = fs::read_to_string(&path) else {
+            return Settings::default();
+        };
+        serde_json::from_str(&source).unwrap_or_else(|err| {
+            eprintln!("ignoring {}: {}", path.display(), err);
+            Settings::default()
+        })
+    }
+}
+
+/** $XDG_CONFIG_HOME/nvim2, falling back to ~/.config */
+pub fn config_dir() -> Option<PathBuf> {
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config.join("nvim2"))
+}
+
+fn settings_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("settings.json"))
+}
+
+pub fn settings(cx: &AppContext) -> &Settings {
+    cx.global::<Settings>()
+}
+
+pub fn metrics(cx: &AppContext) -> LineMetrics {
+    settings(cx).metrics()
+}
+
+pub fn init(cx: &mut AppContext) {
+    cx.set_global(Settings::load());
+}
+
+/** +1/-1 steps the zoom, 0 resets it, every window relayouts */
+pub fn zoom(step: f32, cx: &mut AppContext) {
+    let settings = cx.global_mut::<Settings>();
+    settings.zoom = if step == 0. {
+        0.
+    } else {
+        let size = (settings.font_size + settings.zoom + step).clamp(MIN_FONT_SIZE, MAX_FONT_SIZE);
+        size - settings.font_size
+    };
+    cx.refresh();
+}
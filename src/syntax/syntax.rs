@@ -0,0 +1,183 @@
+use std::{collections::HashMap, ops::Range, path::Path};
+
+use gpui::{Hsla, SharedString};
+use streaming_iterator::StreamingIterator;
+use tree_sitter::{InputEdit, Language, Node, Parser, Point, Query, QueryCursor, Tree};
+
+use crate::text::edit::{BufferEdit, BufferPoint};
+
+pub struct LanguageConfig {
+    pub name: &'static str,
+    pub language: Language,
+    pub highlights: &'static str,
+}
+
+/** picked from the file extension */
+pub fn language_for_path(path: &str) -> Option<LanguageConfig> {
+    let extension = Path::new(path).extension()?.to_str()?;
+    let config = match extension {
+        "rs" => LanguageConfig {
+            name: "rust",
+            language: tree_sitter_rust::LANGUAGE.into(),
+            highlights: tree_sitter_rust::HIGHLIGHTS_QUERY,
+        },
+        "c" | "h" => LanguageConfig {
+            name: "c",
+            language: tree_sitter_c::LANGUAGE.into(),
+            highlights: tree_sitter_c::HIGHLIGHT_QUERY,
+        },
+        "py" | "pyi" => LanguageConfig {
+            name: "python",
+            language: tree_sitter_python::LANGUAGE.into(),
+            highlights: tree_sitter_python::HIGHLIGHTS_QUERY,
+        },
+        _ => return None,
+    };
+    Some(config)
+}
+
+/** part of one line that got a highlight capture, range is in bytes */
+#[derive(Clone, Debug)]
+pub struct HighlightSpan {
+    pub range: Range<usize>,
+    pub color: Hsla,
+}
+
+pub struct Syntax {
+    pub language_name: &'static str,
+    parser: Parser,
+    query: Query,
+    tree: Option<Tree>,
+}
+
+impl Syntax {
+    pub fn for_path(path: &str) -> Option<Syntax> {
+        let config = language_for_path(path)?;
+        let mut parser = Parser::new();
+        parser.set_language(&config.language).ok()?;
+        let query = match Query::new(&config.language, config.highlights) {
+            Ok(query) => query,
+            Err(err) => {
+                eprintln!("bad highlight query for {}: {}", config.name, err);
+                return None;
+            }
+        };
+        Some(Syntax {
+            language_name: config.name,
+            parser,
+            query,
+            tree: None,
+        })
+    }
+
+    /** full parse, drops the old tree */
+    pub fn parse(&mut self, content: &[SharedString]) {
+        self.tree = None;
+        self.reparse(content);
+    }
+
+    /** incremental, only the edited part of the old tree gets reparsed */
+    pub fn edit(&mut self, edit: &BufferEdit, content: &[SharedString]) {
+        if let Some(tree) = self.tree.as_mut() {
+            tree.edit(&InputEdit {
+                start_byte: edit.start_byte,
+                old_end_byte: edit.old_end_byte,
+                new_end_byte: edit.new_end_byte,
+                start_position: to_point(edit.start),
+                old_end_position: to_point(edit.old_end),
+                new_end_position: to_point(edit.new_end),
+            });
+        }
+        self.reparse(content);
+    }
+
+    fn reparse(&mut self, content: &[SharedString]) {
+        // hand tree-sitter the lines one by one instead of joining the whole buffer
+        let mut read = |_byte: usize, point: Point| -> &[u8] {
+            match content.get(point.row) {
+                Some(line) if point.column < line.len() => &line.as_bytes()[point.column..],
+                Some(_) if point.row + 1 < content.len() => b"\n",
+                _ => &[],
+            }
+        };
+        self.tree = self.parser.parse_with(&mut read, self.tree.as_ref());
+    }
+
+    pub fn tree(&self) -> Option<&Tree> {
+        self.tree.as_ref()
+    }
+
+    /** strictly inside a string or comment, right after the closing quote doesn't count */
+    pub fn in_string_or_comment(&self, point: BufferPoint) -> bool {
+        let Some(tree) = self.tree.as_ref() else {
+            return false;
+        };
+        let point = to_point(point);
+        let mut node = tree.root_node().descendant_for_point_range(point, point);
+        while let Some(current) = node {
+            let kind = current.kind();
+            let literal = kind.contains("string") || kind.contains("comment") || kind == "char_literal";
+            if literal && current.start_position() < point && point < current.end_position() {
+                return true;
+            }
+            node = current.parent();
+        }
+        false
+    }
+
+    /** highlight spans for each line in `lines`, colors come from `color_for(capture name)` */
+    pub fn highlights(
+        &self,
+        content: &[SharedString],
+        lines: Range<usize>,
+        color_for: impl Fn(&str) -> Option<Hsla>,
+    ) -> HashMap<usize, Vec<HighlightSpan>> {
+        let mut spans: HashMap<usize, Vec<HighlightSpan>> = HashMap::new();
+        let Some(tree) = self.tree.as_ref() else {
+            return spans;
+        };
+
+        let node_text = |node: Node| {
+            let (start, end) = (node.start_position(), node.end_position());
+            (start.row..=end.row).map(move |row| {
+                let line = content.get(row).map(|line| line.as_bytes()).unwrap_or(&[]);
+                let from = if row == start.row { start.column.min(line.len()) } else { 0 };
+                let to = if row == end.row { end.column.min(line.len()) } else { line.len() };
+                let mut text = line[from.min(to)..to].to_vec();
+                if row != end.row {
+                    text.push(b'\n');
+                }
+                text
+            })
+        };
+
+        let mut cursor = QueryCursor::new();
+        cursor.set_point_range(Point::new(lines.start, 0)..Point::new(lines.end, 0));
+        let names = self.query.capture_names();
+        let mut captures = cursor.captures(&self.query, tree.root_node(), node_text);
+        while let Some((found, index)) = captures.next() {
+            let capture = found.captures[*index];
+            let Some(color) = color_for(names[capture.index as usize]) else {
+                continue;
+            };
+            let (start, end) = (capture.node.start_position(), capture.node.end_position());
+            // multi line nodes (block comments, strings) get split per line
+            for row in start.row.max(lines.start)..=end.row.min(lines.end.saturating_sub(1)) {
+                let len = content.get(row).map(|line| line.len()).unwrap_or(0);
+                let from = if row == start.row { start.column } else { 0 };
+                let to = if row == end.row { end.column } else { len };
+                if from < to {
+                    spans.entry(row).or_default().push(HighlightSpan {
+                        range: from.min(len)..to.min(len),
+                        color,
+                    });
+                }
+            }
+        }
+        spans
+    }
+}
+
+fn to_point(point: BufferPoint) -> Point {
+    Point::new(point.line, point.col)
+}
@@ -0,0 +1,157 @@
+use std::ops::Range;
+
+use super::{edit::BufferPoint, text::TextInput};
+
+/** (open, close) pairs typing `open` completes, quotes close with themselves */
+fn pairs_for(language: Option<&str>) -> &'static [(char, char)] {
+    match language {
+        Some("rust") | Some("c") | Some("python") => &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')],
+        _ => &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\''), ('`', '`')],
+    }
+}
+
+/** python's f"", rb"" etc, the letters before the quote don't make it a word */
+fn is_string_prefix(word: &str, language: Option<&str>) -> bool {
+    language == Some("python")
+        && matches!(word.to_lowercase().as_str(), "f" | "r" | "b" | "u" | "rb" | "br" | "fr" | "rf")
+}
+
+/**
+ * rust's ' starts lifetimes and labels as often as char literals. &'a, <'a, `'a, 'b`,
+ * `break 'outer` and `T: 'static` don't get a closing quote
+ */
+fn starts_lifetime(before: &str) -> bool {
+    if before.ends_with(['&', '<', ',']) {
+        return true;
+    }
+    let trimmed = before.trim_end();
+    trimmed.len() < before.len()
+        && trimmed.ends_with(|c: char| c.is_alphanumeric() || matches!(c, '_' | ',' | '<' | ':' | '+'))
+}
+
+// closing brackets and quotes as you type, removed again by backspace while still empty
+impl TextInput {
+    /**
+     * called for plain typing, returns true when the keystroke was handled here: stepping
+     * over an auto inserted closer, wrapping the selection, or inserting the pair
+     */
+    pub fn autopair(&mut self, range: Range<usize>, typed: &str) -> bool {
+        let mut chars = typed.chars();
+        let (Some(typed), None) = (chars.next(), chars.next()) else {
+            return false;
+        };
+        if self.auto_closed_line != self.focused_line {
+            self.auto_closed.clear();
+            self.auto_closed_line = self.focused_line;
+        }
+        let line = self.content[self.focused_line].clone();
+        let language = self.syntax.as_ref().map(|syntax| syntax.language_name);
+        let pairs = pairs_for(language);
+
+        // typing the closer we put there just moves past it
+        let next = line[range.end..].chars().next();
+        if range.is_empty() && next == Some(typed) && pairs.iter().any(|(_, close)| *close == typed) {
+            if let Some(ix) = self.auto_closed.iter().position(|offset| *offset == range.start) {
+                self.auto_closed.remove(ix);
+                let offset = range.start + typed.len_utf8();
+                self.selected_range = offset..offset;
+                self.cursor_pos = offset;
+                return true;
+            }
+        }
+
+        let Some((open, close)) = pairs.iter().copied().find(|(open, _)| *open == typed) else {
+            return false;
+        };
+        let (open, close) = (open.to_string(), close.to_string());
+
+        // selection on one line gets wrapped, keeping the selection on the same text
+        if !range.is_empty() && self.selected_line_span().len() == 1 {
+            self.edit_line(self.focused_line, range.end..range.end, &close);
+            self.edit_line(self.focused_line, range.start..range.start, &open);
+            self.selected_range = range.start + open.len()..range.end + open.len();
+            self.cursor_pos = self.cursor_offset();
+            return true;
+        }
+        if !range.is_empty() {
+            return false;
+        }
+
+        let before = &line[..range.start];
+        let prev = before.chars().last();
+        // typing in front of a word usually means the closer belongs somewhere else
+        if next.is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            return false;
+        }
+        if open == close {
+            let word_start = before
+                .char_indices()
+                .rev()
+                .take_while(|(_, c)| c.is_alphanumeric() || *c == '_')
+                .last()
+                .map(|(ix, _)| ix)
+                .unwrap_or(range.start);
+            let word = &before[word_start..];
+            // don't, it's
+            if !word.is_empty() && !is_string_prefix(word, language) {
+                return false;
+            }
+            if typed == '\'' && language == Some("rust") && starts_lifetime(before) {
+                return false;
+            }
+            let point = BufferPoint::new(self.focused_line, range.start);
+            if self.syntax.as_ref().is_some_and(|syntax| syntax.in_string_or_comment(point)) {
+                return false;
+            }
+        } else if prev == Some('\\') {
+            return false;
+        }
+
+        self.edit_line(self.focused_line, range.start..range.start, &(open.clone() + &close));
+        let offset = range.start + open.len();
+        for auto_closed in self.auto_closed.iter_mut().filter(|auto_closed| **auto_closed >= range.start) {
+            *auto_closed += open.len() + close.len();
+        }
+        self.auto_closed.push(offset);
+        self.selected_range = offset..offset;
+        self.cursor_pos = offset;
+        true
+    }
+
+    /** auto inserted closers after an edit on the focused line */
+    pub fn shift_auto_closed(&mut self, replaced: Range<usize>, inserted: usize) {
+        if self.auto_closed_line != self.focused_line {
+            return;
+        }
+        self.auto_closed.retain(|offset| *offset < replaced.start || *offset >= replaced.end);
+        for offset in self.auto_closed.iter_mut().filter(|offset| **offset >= replaced.end) {
+            *offset = *offset - replaced.len() + inserted;
+        }
+    }
+
+    /** backspace between `(` and a `)` autopair inserted (or a pair of quotes) takes both */
+    pub fn delete_empty_pair(&mut self) -> bool {
+        let cursor = self.cursor_offset();
+        if !self.selected_range.is_empty() || cursor == 0 {
+            return false;
+        }
+        // a pair the user typed, or one that was already in the file, stays
+        if self.auto_closed_line != self.focused_line || !self.auto_closed.contains(&cursor) {
+            return false;
+        }
+        let line = self.content[self.focused_line].clone();
+        let language = self.syntax.as_ref().map(|syntax| syntax.language_name);
+        let (Some(prev), Some(next)) = (line[..cursor].chars().last(), line[cursor..].chars().next()) else {
+            return false;
+        };
+        if !pairs_for(language).contains(&(prev, next)) {
+            return false;
+        }
+        let start = cursor - prev.len_utf8();
+        self.edit_line(self.focused_line, start..cursor + next.len_utf8(), "");
+        self.shift_auto_closed(start..cursor + next.len_utf8(), 0);
+        self.selected_range = start..start;
+        self.cursor_pos = start;
+        true
+    }
+}
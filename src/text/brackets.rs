@@ -0,0 +1,158 @@
+use std::ops::Range;
+
+use gpui::{actions, Hsla, ViewContext};
+
+use crate::{syntax::syntax::HighlightSpan, theme::theme::Theme};
+
+use super::{edit::BufferPoint, text::TextInput};
+
+actions!(brackets, [JumpToBracket]);
+
+const MAX_SCAN_LINES: usize = 10_000; // give up on a partner further away than this
+// rainbow colors come from these scopes of the active theme
+const RAINBOW_SCOPES: [&str; 6] = ["keyword", "function", "type", "string", "constant", "property"];
+
+/** the other half of a bracket and whether it comes after it */
+fn partner(c: char) -> Option<(char, bool)> {
+    match c {
+        '(' => Some((')', true)),
+        '[' => Some((']', true)),
+        '{' => Some(('}', true)),
+        ')' => Some(('(', false)),
+        ']' => Some(('[', false)),
+        '}' => Some(('{', false)),
+        _ => None,
+    }
+}
+
+impl TextInput {
+    /** brackets in strings and comments don't pair with code, only known with a grammar */
+    fn is_code(&self, point: BufferPoint) -> bool {
+        !self.syntax.as_ref().is_some_and(|syntax| syntax.in_string_or_comment(point))
+    }
+
+    /** the bracket under the cursor, or just before it, and its partner */
+    pub fn matching_bracket(&self) -> Option<(BufferPoint, BufferPoint)> {
+        let line = &self.content[self.focused_line];
+        let cursor = self.cursor_offset().min(line.len());
+        let before = line[..cursor].char_indices().last().map(|(ix, _)| ix);
+
+        for col in [Some(cursor), before].into_iter().flatten() {
+            let Some(c) = line[col..].chars().next() else {
+                continue;
+            };
+            let Some((other, forward)) = partner(c) else {
+                continue;
+            };
+            let point = BufferPoint::new(self.focused_line, col);
+            if !self.is_code(point) {
+                continue;
+            }
+            if let Some(found) = self.scan_for_partner(point, c, other, forward) {
+                return Some((point, found));
+            }
+        }
+        None
+    }
+
+    fn scan_for_partner(&self, from: BufferPoint, this: char, other: char, forward: bool) -> Option<BufferPoint> {
+        let mut depth = 0;
+        let lines: Box<dyn Iterator<Item = usize>> = if forward {
+            Box::new(from.line..self.lines.min(from.line + MAX_SCAN_LINES))
+        } else {
+            Box::new((from.line.saturating_sub(MAX_SCAN_LINES)..=from.line).rev())
+        };
+
+        for line in lines {
+            let text = &self.content[line];
+            let chars: Box<dyn Iterator<Item = (usize, char)>> = match (forward, line == from.line) {
+                (true, true) => Box::new(text.char_indices().filter(|(ix, _)| *ix > from.col)),
+                (true, false) => Box::new(text.char_indices()),
+                (false, true) => Box::new(text.char_indices().rev().filter(|(ix, _)| *ix < from.col)),
+                (false, false) => Box::new(text.char_indices().rev()),
+            };
+            for (col, c) in chars {
+                if c != this && c != other {
+                    continue;
+                }
+                let point = BufferPoint::new(line, col);
+                if !self.is_code(point) {
+                    continue;
+                }
+                if c == this {
+                    depth += 1;
+                } else if depth == 0 {
+                    return Some(point);
+                } else {
+                    depth -= 1;
+                }
+            }
+        }
+        None
+    }
+
+    /** % in vim, ctrl-shift-\ in vscode */
+    pub fn jump_to_bracket(&mut self, _: &JumpToBracket, cx: &mut ViewContext<Self>) {
+        let Some((_, partner)) = self.matching_bracket() else {
+            return;
+        };
+        self.focused_line = partner.line;
+        self.selected_lines = 0..0;
+        self.selection_reversed = false;
+        self.marked_range = None;
+        self.move_to(partner.col, cx);
+    }
+
+    /** calls `f(col, depth)` for each code bracket on the line, returns the depth after it */
+    fn walk_brackets(&self, line: usize, mut depth: usize, mut f: impl FnMut(usize, usize)) -> usize {
+        for (col, c) in self.content[line].char_indices() {
+            let Some((_, opens)) = partner(c) else {
+                continue;
+            };
+            if !self.is_code(BufferPoint::new(line, col)) {
+                continue;
+            }
+            if opens {
+                f(col, depth);
+                depth += 1;
+            } else {
+                depth = depth.saturating_sub(1);
+                f(col, depth);
+            }
+        }
+        depth
+    }
+
+    /** nesting depth where `line` starts, filled in from the last line still known after edits */
+    fn bracket_depth_at(&mut self, line: usize) -> usize {
+        if self.bracket_depths.is_empty() {
+            self.bracket_depths.push(0);
+        }
+        while self.bracket_depths.len() <= line {
+            let last = self.bracket_depths.len() - 1;
+            let depth = self.walk_brackets(last, self.bracket_depths[last], |_, _| {});
+            self.bracket_depths.push(depth);
+        }
+        self.bracket_depths[line]
+    }
+
+    /** colors brackets in `lines` by depth, in front so they win over the grammar's colors */
+    pub fn add_rainbow_highlights(&mut self, lines: Range<usize>, theme: &Theme) {
+        let colors: Vec<Hsla> = RAINBOW_SCOPES.iter().filter_map(|scope| theme.syntax_color(scope)).collect();
+        if colors.is_empty() || lines.is_empty() {
+            return;
+        }
+        let mut depth = self.bracket_depth_at(lines.start);
+        for line in lines {
+            let mut spans = vec![];
+            depth = self.walk_brackets(line, depth, |col, depth| {
+                spans.push(HighlightSpan {
+                    range: col..col + 1,
+                    color: colors[depth % colors.len()],
+                });
+            });
+            let highlights = self.highlights.entry(line).or_default();
+            highlights.splice(0..0, spans);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use super::text::TextInput;
+
+/** cells a grapheme takes starting at `column`, tabs run to the next tab stop */
+fn grapheme_width(grapheme: &str, column: usize, tab_size: usize) -> usize {
+    if grapheme == "\t" {
+        let tab_size = tab_size.max(1);
+        return tab_size - column % tab_size;
+    }
+    grapheme.width()
+}
+
+/** display column of the byte `offset`, offsets inside a grapheme count from its start */
+pub fn column_at(line: &str, offset: usize, tab_size: usize) -> usize {
+    let mut column = 0;
+    for (start, grapheme) in line.grapheme_indices(true) {
+        if start + grapheme.len() > offset {
+            break;
+        }
+        column += grapheme_width(grapheme, column, tab_size);
+    }
+    column
+}
+
+/**
+ * start of the grapheme covering `column`, so landing in the middle of a tab or a wide char
+ * puts the cursor on it, past the end clamps to the end. always a char boundary
+ */
+pub fn offset_for_column(line: &str, column: usize, tab_size: usize) -> usize {
+    let mut current = 0;
+    for (start, grapheme) in line.grapheme_indices(true) {
+        let width = grapheme_width(grapheme, current, tab_size);
+        if current + width > column {
+            return start;
+        }
+        current += width;
+    }
+    line.len()
+}
+
+impl TextInput {
+    /**
+     * where up/down should land on `line`. the column sticks across lines that are too short,
+     * and is only recomputed once the cursor moved some other way
+     */
+    pub fn offset_for_goal_column(&mut self, line: usize) -> usize {
+        let tab_size = self.indent_style.tab_size;
+        let offset = self.cursor_offset();
+        let column = match self.goal_column {
+            Some((goal_line, goal_offset, column)) if goal_line == self.focused_line && goal_offset == offset => column,
+            _ => column_at(&self.content[self.focused_line], offset, tab_size),
+        };
+        let target = offset_for_column(&self.content[line], column, tab_size);
+        self.goal_column = Some((line, target, column));
+        target
+    }
+}
@@ -0,0 +1,208 @@
+use gpui::{actions, ViewContext};
+
+use crate::files::{
+    encoding::{encode, from_label},
+    files::ReadOnly,
+    format::LineEnding,
+};
+
+use crate::{
+    project::search::{ProjectQuery, ProjectSearchEvent},
+    settings::settings::{LineNumbers, Settings},
+    theme::theme::ThemeRegistry,
+};
+
+use super::text::TextInput;
+
+actions!(
+    command,
+    [
+        OpenCommandLine,
+        RunCommand,
+        CloseCommandLine,
+        CommandBackspace,
+    ]
+);
+
+// vim style `:` commands, typed into the status line
+impl TextInput {
+    pub fn open_command_line(&mut self, _: &OpenCommandLine, cx: &mut ViewContext<Self>) {
+        self.command_line = Some(String::new());
+        cx.notify();
+    }
+
+    pub fn close_command_line(&mut self, _: &CloseCommandLine, cx: &mut ViewContext<Self>) {
+        self.command_line = None;
+        cx.notify();
+    }
+
+    pub fn command_backspace(&mut self, _: &CommandBackspace, cx: &mut ViewContext<Self>) {
+        if let Some(command) = self.command_line.as_mut() {
+            if command.pop().is_none() {
+                // backspace on an empty line leaves, like vim
+                self.command_line = None;
+            }
+        }
+        cx.notify();
+    }
+
+    pub fn run_command(&mut self, _: &RunCommand, cx: &mut ViewContext<Self>) {
+        let Some(command) = self.command_line.take() else {
+            return;
+        };
+        if let Err(err) = self.execute_command(command.trim(), cx) {
+            self.status_message = Some(err.into());
+        }
+        cx.notify();
+    }
+
+    pub fn execute_command(&mut self, command: &str, cx: &mut ViewContext<Self>) -> Result<(), String> {
+        let mut parts = command.split_whitespace();
+        match parts.next() {
+            None => Ok(()),
+            Some("w") | Some("write") => {
+                self.write_file(false, cx);
+                Ok(())
+            }
+            Some("w!") | Some("write!") => {
+                self.write_file(true, cx);
+                Ok(())
+            }
+            Some("e!") | Some("edit!") => {
+                self.reload(cx);
+                Ok(())
+            }
+            Some("colorscheme") | Some("colo") | Some("theme") => {
+                let Some(name) = parts.next() else {
+                    let name = cx.global::<ThemeRegistry>().active().name.clone();
+                    self.status_message = Some(format!("colorscheme={}", name).into());
+                    return Ok(());
+                };
+                cx.update_global::<ThemeRegistry, _>(|registry, _| registry.set_active(name))?;
+                cx.refresh();
+                Ok(())
+            }
+            Some("grep") | Some("vimgrep") => {
+                let args = command.split_once(char::is_whitespace).map(|(_, args)| args).unwrap_or_default();
+                cx.emit(ProjectSearchEvent::Search(ProjectQuery::parse(args)?));
+                Ok(())
+            }
+            Some("greplace") => {
+                let replacement = command.split_once(char::is_whitespace).map(|(_, text)| text).unwrap_or_default();
+                cx.emit(ProjectSearchEvent::Replace(replacement.to_string()));
+                Ok(())
+            }
+            Some("gapply") => {
+                cx.emit(ProjectSearchEvent::Apply);
+                Ok(())
+            }
+            Some("cclose") | Some("ccl") => {
+                cx.emit(ProjectSearchEvent::Close);
+                Ok(())
+            }
+            Some("noh") | Some("nohlsearch") => {
+                self.search = None;
+                self.search_task = None;
+                Ok(())
+            }
+            Some("set") => {
+                for option in parts {
+                    self.set_option(option, cx)?;
+                }
+                Ok(())
+            }
+            Some(other) => Err(format!("not an editor command: {}", other)),
+        }
+    }
+
+    fn set_option(&mut self, option: &str, cx: &mut ViewContext<Self>) -> Result<(), String> {
+        let (name, value) = match option.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (option, None),
+        };
+
+        match (name, value) {
+            ("fileformat" | "ff", Some(value)) => {
+                self.format.line_ending = LineEnding::from_name(value)
+                    .ok_or_else(|| format!("invalid fileformat: {}", value))?;
+            }
+            ("fileformat" | "ff", None) => {
+                self.status_message = Some(format!("fileformat={}", self.format.line_ending).into());
+            }
+            ("fileencoding" | "fenc", Some(value)) => {
+                let encoding = from_label(value)
+                    .ok_or_else(|| format!("unknown fileencoding: {}", value))?;
+                match self.read_only {
+                    Some(ReadOnly::View | ReadOnly::Unreadable) => return Err("buffer is read only".to_string()),
+                    // the U+FFFDs would be saved, the bytes they replaced have to be read again
+                    Some(ReadOnly::Lossy) => {
+                        self.reload_as(encoding, cx);
+                        return Ok(());
+                    }
+                    Some(ReadOnly::RoundTrip) | None => {}
+                }
+                // refuse up front rather than failing on the next save
+                let text = self.content.iter().map(|line| &line[..]).collect::<Vec<&str>>().join("\n");
+                encode(&text, encoding, self.format.bom)?;
+                self.format.encoding = encoding;
+                self.read_only = None;
+            }
+            ("fileencoding" | "fenc", None) => {
+                self.status_message = Some(format!("fileencoding={}", self.format.encoding.name()).into());
+            }
+            ("bomb", None) => self.format.bom = true,
+            ("nobomb", None) => self.format.bom = false,
+            ("backup" | "bk", None) => self.write_backup = true,
+            ("nobackup" | "nobk", None) => self.write_backup = false,
+            ("number" | "nu" | "nonumber" | "nonu" | "relativenumber" | "rnu" | "norelativenumber" | "nornu", None) => {
+                let settings = cx.global_mut::<Settings>();
+                let (mut absolute, mut relative) = match settings.line_numbers {
+                    LineNumbers::Off => (false, false),
+                    LineNumbers::Absolute => (true, false),
+                    LineNumbers::Relative => (false, true),
+                    LineNumbers::Hybrid => (true, true),
+                };
+                match name {
+                    "number" | "nu" => absolute = true,
+                    "nonumber" | "nonu" => absolute = false,
+                    "relativenumber" | "rnu" => relative = true,
+                    _ => relative = false,
+                }
+                // same combinations as vim's number + relativenumber
+                settings.line_numbers = match (absolute, relative) {
+                    (false, false) => LineNumbers::Off,
+                    (true, false) => LineNumbers::Absolute,
+                    (false, true) => LineNumbers::Relative,
+                    (true, true) => LineNumbers::Hybrid,
+                };
+                cx.refresh();
+            }
+            ("tabstop" | "ts", Some(value)) => self.indent_style.tab_size = parse_width(value)?,
+            ("shiftwidth" | "sw", Some(value)) => self.indent_style.indent_width = parse_width(value)?,
+            ("expandtab" | "et", None) => self.indent_style.insert_spaces = true,
+            ("noexpandtab" | "noet", None) => self.indent_style.insert_spaces = false,
+            ("tabstop" | "ts" | "shiftwidth" | "sw", None) => {
+                let style = self.indent_style;
+                self.status_message = Some(format!(
+                    "tabstop={} shiftwidth={} {}",
+                    style.tab_size,
+                    style.indent_width,
+                    if style.insert_spaces { "expandtab" } else { "noexpandtab" }
+                ).into());
+            }
+            ("wrap", None) => self.set_soft_wrap(true, cx),
+            ("nowrap", None) => self.set_soft_wrap(false, cx),
+            ("eol", None) => self.format.final_newline = true,
+            ("noeol", None) => self.format.final_newline = false,
+            _ => return Err(format!("unknown option: {}", option)),
+        }
+        Ok(())
+    }
+}
+
+fn parse_width(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(width) if width > 0 => Ok(width),
+        _ => Err(format!("invalid width: {}", value)),
+    }
+}
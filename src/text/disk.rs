@@ -0,0 +1,259 @@
+use std::{cmp::min, io, path::Path, sync::Arc, time::Duration};
+
+use futures::{
+    channel::oneshot,
+    future::{select, Either},
+    StreamExt,
+};
+use encoding_rs::Encoding;
+use gpui::{PromptLevel, ViewContext, WindowOptions};
+use similar::TextDiff;
+
+use crate::files::{
+    files::{join_lines, load_file_with_progress, read_file, save, LoadProgress, LoadedFile, ReadOnly},
+    format::{split_lines, FileFormat},
+    watcher::{watch_file, DiskStamp},
+};
+
+use crate::settings::settings::settings;
+
+use super::text::TextInput;
+
+// keeping the buffer in sync with the file on disk
+impl TextInput {
+    /** reads and indexes the file off the ui thread, the window opens straight away */
+    pub fn load_in_background(&mut self, cx: &mut ViewContext<Self>) {
+        self.spawn_load(None, Self::loaded, cx);
+    }
+
+    /**
+     * edits are refused until `done` has the lines, the status line shows the progress.
+     * `encoding` overrides detection
+     */
+    fn spawn_load(
+        &mut self,
+        encoding: Option<&'static Encoding>,
+        done: fn(&mut Self, LoadedFile, &mut ViewContext<Self>),
+        cx: &mut ViewContext<Self>,
+    ) {
+        let progress = Arc::new(LoadProgress::default());
+        self.loading = Some(progress.clone());
+        let path = self.open_file.clone();
+
+        self.load_task = Some(cx.spawn(|this, mut cx| async move {
+            let mut load = cx
+                .background_executor()
+                .spawn(async move { load_file_with_progress(path, encoding, &progress) });
+
+            // redraw the progress every so often until the lines are in
+            let loaded = loop {
+                let tick = cx.background_executor().timer(Duration::from_millis(100));
+                match select(load, tick).await {
+                    Either::Left((loaded, _)) => break loaded,
+                    Either::Right((_, pending)) => {
+                        load = pending;
+                        let _ = this.update(&mut cx, |_, cx| cx.notify());
+                    }
+                }
+            };
+
+            let _ = this.update(&mut cx, |this, cx| {
+                this.loading = None;
+                done(this, loaded, cx);
+                cx.notify();
+            });
+        }));
+    }
+
+    fn loaded(&mut self, loaded: LoadedFile, cx: &mut ViewContext<Self>) {
+        self.lines = loaded.lines.len();
+        self.content = loaded.lines;
+        self.format = loaded.format;
+        self.read_only = loaded.read_only;
+        self.status_message = loaded.error.map(Into::into);
+        self.disk_stamp = DiskStamp::read(&self.open_file);
+        self.detect_indent_style(settings(cx));
+        self.content_replaced();
+        if let Some(point) = self.pending_jump.take() {
+            self.jump_to_point(self.clamp_point(point), cx);
+        }
+        self.watch_file(cx);
+        self.attach_lsp(cx);
+    }
+
+    pub fn watch_file(&mut self, cx: &mut ViewContext<Self>) {
+        let (watcher, mut changes) = match watch_file(&self.open_file) {
+            Ok(watch) => watch,
+            Err(err) => {
+                self.status_message = Some(format!("not watching {}: {}", self.open_file, err).into());
+                return;
+            }
+        };
+
+        self.watch_task = Some(cx.spawn(|this, mut cx| async move {
+            let _watcher = watcher; // events stop once this is dropped
+            while changes.next().await.is_some() {
+                // tools write in bursts, wait for them to settle
+                cx.background_executor().timer(Duration::from_millis(50)).await;
+                while let Ok(Some(())) = changes.try_next() {}
+
+                let Ok(prompt) = this.update(&mut cx, |this, cx| this.on_disk_changed(cx)) else {
+                    break;
+                };
+                if let Some(prompt) = prompt {
+                    let answer = prompt.await;
+                    let _ = this.update(&mut cx, |this, cx| match answer {
+                        Ok(0) => this.reload(cx),
+                        Ok(2) => this.show_disk_diff(cx),
+                        _ => {
+                            // disk_stamp stays old so saving is still blocked until :w!
+                            this.status_message = Some("kept buffer, :w! overwrites the file on disk".into());
+                            cx.notify();
+                        }
+                    });
+                }
+            }
+        }));
+    }
+
+    /** clean buffers reload straight away, dirty ones ask first */
+    fn on_disk_changed(&mut self, cx: &mut ViewContext<Self>) -> Option<oneshot::Receiver<usize>> {
+        let stamp = DiskStamp::read(&self.open_file);
+        if stamp == self.disk_stamp {
+            // our own save, or a touch that changed nothing
+            return None;
+        }
+        if stamp.is_none() {
+            self.status_message = Some(format!("{} was deleted on disk", self.open_file).into());
+            cx.notify();
+            return None;
+        }
+        if !self.dirty {
+            self.reload(cx);
+            return None;
+        }
+
+        Some(cx.prompt(
+            PromptLevel::Warning,
+            &format!("{} changed on disk", self.open_file),
+            Some("The buffer has unsaved changes."),
+            &["Reload", "Keep Mine", "Show Diff"],
+        ))
+    }
+
+    /** re-reads the file in the background, keeping the cursor on the same line */
+    pub fn reload(&mut self, cx: &mut ViewContext<Self>) {
+        self.spawn_load(None, Self::reloaded, cx);
+    }
+
+    /** :set fenc on a buffer that was decoded lossily, the file's bytes read as `encoding` */
+    pub fn reload_as(&mut self, encoding: &'static Encoding, cx: &mut ViewContext<Self>) {
+        self.spawn_load(Some(encoding), Self::reloaded, cx);
+    }
+
+    fn reloaded(&mut self, loaded: LoadedFile, cx: &mut ViewContext<Self>) {
+        self.lines = loaded.lines.len();
+        self.content = loaded.lines;
+        self.format = loaded.format;
+        self.read_only = loaded.read_only;
+        self.status_message = Some(
+            loaded.error.unwrap_or_else(|| format!("reloaded {}", self.open_file)).into(),
+        );
+        self.disk_stamp = DiskStamp::read(&self.open_file);
+        self.dirty = false;
+        self.detect_indent_style(settings(cx));
+        self.content_replaced();
+
+        self.focused_line = min(self.focused_line, self.lines - 1);
+        let line = &self.content[self.focused_line];
+        let mut pos = min(self.cursor_pos, line.len());
+        while !line.is_char_boundary(pos) {
+            pos -= 1;
+        }
+        self.cursor_pos = pos;
+        self.selected_range = pos..pos;
+        self.selected_lines = 0..0;
+        self.selected_lines_reversed = false;
+        self.selection_reversed = false;
+        self.marked_range = None;
+        self.is_selecting = false;
+    }
+
+    /** opens a read only window with the unified diff from disk to the buffer */
+    pub fn show_disk_diff(&mut self, cx: &mut ViewContext<Self>) {
+        let ours = join_lines(&self.content, FileFormat::default());
+        let open_file = self.open_file.clone();
+        cx.spawn(|_, mut cx| async move {
+            // the file can be big, reading and diffing stay off the ui thread
+            let diff = cx
+                .background_executor()
+                .spawn({
+                    let open_file = open_file.clone();
+                    async move {
+                        let disk = match read_file(Path::new(&open_file)) {
+                            Ok(loaded) => join_lines(&loaded.lines, FileFormat::default()),
+                            // deleted on disk, the whole buffer shows as added
+                            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+                            Err(err) => return format!("can't read {}: {}", open_file, err),
+                        };
+                        TextDiff::from_lines(&disk, &ours)
+                            .unified_diff()
+                            .header(&format!("{} (disk)", open_file), &format!("{} (buffer)", open_file))
+                            .to_string()
+                    }
+                })
+                .await;
+
+            let loaded = LoadedFile {
+                lines: split_lines(&diff).0.into_iter().map(|line| line.to_string().into()).collect(),
+                format: FileFormat::default(),
+                error: Some("diff view is read only".into()),
+                read_only: Some(ReadOnly::View),
+            };
+            let path = format!("{}.diff", open_file);
+            let _ = cx.update(|cx| {
+                let window = cx.open_window(WindowOptions::default(), |cx| {
+                    cx.new_view(|cx| TextInput::new(cx.focus_handle(), path, loaded, settings(cx)))
+                });
+                if let Ok(window) = window {
+                    let _ = window.update(cx, |view, cx| cx.focus(&view.focus_handle));
+                }
+            });
+        })
+        .detach();
+    }
+
+    /** refuses to clobber a file that changed on disk since we read it, unless forced (:w!) */
+    pub fn write_file(&mut self, force: bool, cx: &mut ViewContext<Self>) {
+        if self.refuse_edit(cx) {
+            return;
+        }
+        let on_disk = DiskStamp::read(&self.open_file);
+        if !force && on_disk.is_some() && on_disk != self.disk_stamp {
+            self.status_message = Some(format!(
+                "{} changed on disk since it was loaded, :e! reloads and :w! overwrites",
+                self.open_file
+            ).into());
+            cx.notify();
+            return;
+        }
+
+        let result = save(self.open_file.clone(),
+            self.content.clone(),
+            self.format,
+            self.write_backup,
+        );
+        self.status_message = Some(match result {
+            Ok(()) => {
+                self.dirty = false;
+                self.disk_stamp = DiskStamp::read(&self.open_file);
+                if let Some(document) = self.lsp.as_ref() {
+                    document.saved(&self.content);
+                }
+                format!("saved {}", self.open_file).into()
+            }
+            Err(err) => format!("failed to save {}: {}", self.open_file, err).into(),
+        });
+        cx.notify();
+    }
+}
@@ -0,0 +1,125 @@
+use std::ops::Range;
+
+use gpui::SharedString;
+
+use crate::lsp::document::{text_between, utf16_len};
+
+use super::text::TextInput;
+
+/** line + byte column in the buffer */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BufferPoint {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl BufferPoint {
+    pub fn new(line: usize, col: usize) -> Self {
+        BufferPoint { line, col }
+    }
+}
+
+/** one contiguous replacement, old_end/new_end are where the replaced and the new text end */
+#[derive(Clone, Copy, Debug)]
+pub struct BufferEdit {
+    pub start: BufferPoint,
+    pub old_end: BufferPoint,
+    pub new_end: BufferPoint,
+    pub start_byte: usize,
+    pub old_end_byte: usize,
+    pub new_end_byte: usize,
+    pub old_end_utf16: usize, // old_end.col in utf-16 units, for lsp, the old line is gone after the edit
+}
+
+// every change to `content` goes through begin_edit/finish_edit so syntax etc can follow along
+impl TextInput {
+    /** offset into the buffer as if the lines were joined with \n */
+    pub fn byte_offset(&self, point: BufferPoint) -> usize {
+        self.content[..point.line].iter().map(|line| line.len() + 1).sum::<usize>() + point.col
+    }
+
+    /** call before touching content, old_end is measured against the old text */
+    pub fn begin_edit(&mut self, start: BufferPoint, old_end: BufferPoint) -> BufferEdit {
+        self.record_old_text(text_between(&self.content, start, old_end));
+        let start_byte = self.byte_offset(start);
+        BufferEdit {
+            start,
+            old_end,
+            new_end: start,
+            start_byte,
+            old_end_byte: self.byte_offset(old_end),
+            new_end_byte: start_byte,
+            old_end_utf16: utf16_len(&self.content[old_end.line][..old_end.col]),
+        }
+    }
+
+    /** call once content holds the new text */
+    pub fn finish_edit(&mut self, mut edit: BufferEdit, new_end: BufferPoint) {
+        edit.new_end = new_end;
+        edit.new_end_byte = self.byte_offset(new_end);
+        // lines after the edit start may have a different depth now
+        self.bracket_depths.truncate(edit.start.line + 1);
+        self.bracket_match_at = None;
+        self.shift_diagnostics(&edit);
+        self.record_edit(edit.start, text_between(&self.content, edit.start, new_end));
+        if let Some(search) = self.search.as_mut() {
+            search.stale = true;
+        }
+        if let Some(syntax) = self.syntax.as_mut() {
+            syntax.edit(&edit, &self.content);
+        }
+        if let Some(document) = self.lsp.as_mut() {
+            document.changed(&edit, &self.content);
+        }
+    }
+
+    /** replaces `range` within one line, the cursor is left alone */
+    pub fn edit_line(&mut self, line: usize, range: Range<usize>, text: &str) {
+        let edit = self.begin_edit(BufferPoint::new(line, range.start), BufferPoint::new(line, range.end));
+        let old = &self.content[line];
+        self.content[line] = (old[..range.start].to_owned() + text + &old[range.end..]).into();
+        self.finish_edit(edit, BufferPoint::new(line, range.start + text.len()));
+    }
+
+    /** replaces start..end, which can span lines, with `text` which can hold \n. returns where it ends */
+    pub fn replace_range(&mut self, start: BufferPoint, end: BufferPoint, text: &str) -> BufferPoint {
+        let edit = self.begin_edit(start, end);
+        let suffix = &self.content[end.line][end.col..];
+        let joined = self.content[start.line][..start.col].to_owned() + text + suffix;
+        let suffix_len = suffix.len();
+        let new_lines: Vec<SharedString> = joined.split('\n').map(|line| line.to_string().into()).collect();
+        let new_end = BufferPoint::new(start.line + new_lines.len() - 1, new_lines[new_lines.len() - 1].len() - suffix_len);
+        self.content.splice(start.line..=end.line, new_lines);
+        self.lines = self.content.len();
+        self.finish_edit(edit, new_end);
+        new_end
+    }
+
+    /** nearest point that exists, for positions that came from outside the buffer */
+    pub fn clamp_point(&self, point: BufferPoint) -> BufferPoint {
+        let line = point.line.min(self.lines - 1);
+        let text = &self.content[line];
+        let mut col = point.col.min(text.len());
+        while !text.is_char_boundary(col) {
+            col -= 1;
+        }
+        BufferPoint::new(line, col)
+    }
+
+    /** whole buffer replaced (load, reload) */
+    pub fn content_replaced(&mut self) {
+        // positions in the history are meaningless against other text
+        self.history.clear();
+        self.bracket_depths.clear();
+        self.bracket_match_at = None;
+        if let Some(search) = self.search.as_mut() {
+            search.stale = true;
+        }
+        if let Some(syntax) = self.syntax.as_mut() {
+            syntax.parse(&self.content);
+        }
+        if let Some(document) = self.lsp.as_mut() {
+            document.replaced(&self.content);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::cmp::{max, min};
+
+use gpui::{div, prelude::*, px, Div, MouseButton, MouseDownEvent, Pixels, ViewContext};
+
+use crate::{
+    settings::settings::{metrics, settings, LineMetrics, LineNumbers},
+    theme::theme::Theme,
+};
+
+use super::text::TextInput;
+
+/** widths of the gutter columns, sized once per render from the digit count */
+#[derive(Clone, Copy, Debug)]
+pub struct GutterLayout {
+    pub sign_width: Pixels,
+    pub number_width: Pixels,
+    pub fold_width: Pixels,
+}
+
+impl GutterLayout {
+    pub fn new(lines: usize, char_width: Pixels, line_numbers: LineNumbers) -> Self {
+        let digits = max(3, lines.to_string().len());
+        GutterLayout {
+            sign_width: char_width * 1.5,
+            number_width: match line_numbers {
+                LineNumbers::Off => px(0.),
+                _ => char_width * (digits + 1) as f32,
+            },
+            fold_width: char_width,
+        }
+    }
+
+    pub fn width(&self) -> Pixels {
+        self.sign_width + self.number_width + self.fold_width
+    }
+}
+
+impl TextInput {
+    pub fn line_number_label(&self, line: usize, line_numbers: LineNumbers) -> String {
+        let distance = line.abs_diff(self.focused_line);
+        match line_numbers {
+            LineNumbers::Off => String::new(),
+            LineNumbers::Absolute => (line + 1).to_string(),
+            LineNumbers::Relative => distance.to_string(),
+            // current line keeps its real number
+            LineNumbers::Hybrid if distance == 0 => (line + 1).to_string(),
+            LineNumbers::Hybrid => distance.to_string(),
+        }
+    }
+
+    pub fn render_gutter_cell(
+        &self,
+        line: usize,
+        layout: GutterLayout,
+        line_numbers: LineNumbers,
+        theme: &Theme,
+        cx: &mut ViewContext<Self>,
+    ) -> Div {
+        // the sign column only shows diagnostics so far, git and breakpoints have no source yet
+        let sign_color = self.line_severity(line).map(|severity| severity.color(theme));
+        let number_color = if line == self.focused_line {
+            theme.gutter_active_foreground
+        } else {
+            theme.gutter_foreground
+        };
+
+        div()
+            .flex()
+            .flex_row()
+            .flex_none()
+            .w(layout.width())
+            .bg(theme.gutter_background)
+            .on_mouse_down(
+                MouseButton::Left,
+                cx.listener(move |this, event: &MouseDownEvent, cx| {
+                    this.gutter_mouse_down(line, event, cx);
+                    cx.stop_propagation();
+                }),
+            )
+            .child(
+                div()
+                    .flex_none()
+                    .w(layout.sign_width)
+                    .text_color(sign_color.unwrap_or(theme.gutter_foreground))
+                    .child(if sign_color.is_some() { "●" } else { "" }),
+            )
+            .child(
+                div()
+                    .flex()
+                    .flex_none()
+                    .justify_end()
+                    .w(layout.number_width)
+                    .text_color(number_color)
+                    .child(self.line_number_label(line, line_numbers)),
+            )
+            // fold markers go here once there is folding
+            .child(div().flex_none().w(layout.fold_width))
+    }
+
+    /** click selects the whole line, shift-click extends from the current line */
+    fn gutter_mouse_down(&mut self, line: usize, event: &MouseDownEvent, cx: &mut ViewContext<Self>) {
+        let anchor = if event.modifiers.shift { self.focused_line } else { line };
+        self.gutter_drag_anchor = Some(anchor);
+        self.select_lines(anchor, line, cx);
+    }
+
+    /** drag from the gutter, selects every line between the anchor and the mouse */
+    pub fn gutter_drag(&mut self, y: Pixels, metrics: LineMetrics, cx: &mut ViewContext<Self>) -> bool {
+        let Some(anchor) = self.gutter_drag_anchor else {
+            return false;
+        };
+        let line = self.line_at_y(y, metrics);
+        self.select_lines(anchor, line, cx);
+        true
+    }
+
+    /** full lines from..=to in either order, the cursor ends on the last one */
+    pub fn select_lines(&mut self, from: usize, to: usize, cx: &mut ViewContext<Self>) {
+        let (first, last) = (min(from, to), max(from, to));
+        self.focused_line = last;
+        self.selected_lines = first..last + 1;
+        self.selected_lines_reversed = false;
+        self.selected_range = 0..self.content[last].len();
+        self.selection_reversed = false;
+        self.cursor_pos = self.content[last].len();
+        self.marked_range = None;
+        // keeps edits treating this as a selection
+        self.is_selecting = true;
+        cx.notify();
+    }
+
+    /** one char of the editor font, gutter columns are sized in these */
+    pub fn char_width(cx: &mut ViewContext<Self>) -> Pixels {
+        let metrics = metrics(cx);
+        let font = settings(cx).font();
+        let text_system = cx.text_system();
+        let font_id = text_system.resolve_font(&font);
+        text_system
+            .advance(font_id, metrics.font_size, '0')
+            .map(|size| size.width)
+            .unwrap_or(metrics.font_size * 0.6)
+    }
+}
@@ -0,0 +1,281 @@
+use std::{collections::HashMap, ops::Range};
+
+use gpui::{actions, SharedString, ViewContext, ViewInputHandler};
+
+use crate::settings::settings::Settings;
+
+use super::{column::column_at, edit::BufferPoint, text::TextInput};
+
+actions!(indent, [Indent, Outdent]);
+
+const DETECT_SAMPLE: usize = 1000; // lines looked at to guess a file's indentation
+
+/** per buffer, starts from the settings and whatever the file already uses */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IndentStyle {
+    pub tab_size: usize,
+    pub indent_width: usize,
+    pub insert_spaces: bool,
+}
+
+impl IndentStyle {
+    /** what buffers use before detection, or when it finds nothing */
+    pub fn from_settings(settings: &Settings) -> Self {
+        IndentStyle {
+            tab_size: settings.tab_size.max(1),
+            indent_width: settings.indent_width.max(1),
+            insert_spaces: settings.insert_spaces,
+        }
+    }
+
+    pub fn for_buffer(lines: &[SharedString], settings: &Settings) -> Self {
+        let mut style = IndentStyle::from_settings(settings);
+        if !settings.detect_indent {
+            return style;
+        }
+        match detect_indent(lines) {
+            Some(Detected::Tabs) => {
+                style.insert_spaces = false;
+                style.indent_width = style.tab_size;
+            }
+            Some(Detected::Spaces(width)) => {
+                style.insert_spaces = true;
+                style.indent_width = width;
+            }
+            None => {}
+        }
+        style
+    }
+
+    /** one level of indentation */
+    pub fn unit(&self) -> String {
+        if self.insert_spaces {
+            " ".repeat(self.indent_width)
+        } else {
+            "\t".to_string()
+        }
+    }
+
+    /** bytes of leading whitespace one outdent removes */
+    fn outdent_len(&self, line: &str) -> usize {
+        if line.starts_with('\t') {
+            return 1;
+        }
+        line.bytes().take(self.indent_width).take_while(|b| *b == b' ').count()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Detected {
+    Tabs,
+    Spaces(usize),
+}
+
+pub fn leading_whitespace(line: &str) -> &str {
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+/**
+ * tabs vs spaces by majority of indented lines, the width by the most common step between
+ * neighbouring lines. single space steps are usually alignment (` * ` in comments) so skipped
+ */
+pub fn detect_indent(lines: &[SharedString]) -> Option<Detected> {
+    let (mut tabs, mut spaces) = (0, 0);
+    let mut steps: HashMap<usize, usize> = HashMap::new();
+    let mut previous = 0;
+
+    for line in lines.iter().take(DETECT_SAMPLE) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let indent = leading_whitespace(line);
+        if indent.starts_with('\t') {
+            tabs += 1;
+            previous = 0;
+            continue;
+        }
+        let width = indent.len();
+        if width > 0 {
+            spaces += 1;
+        }
+        let step = width.abs_diff(previous);
+        if (2..=8).contains(&step) {
+            *steps.entry(step).or_default() += 1;
+        }
+        previous = width;
+    }
+
+    if tabs > spaces {
+        return Some(Detected::Tabs);
+    }
+    steps
+        .into_iter()
+        // smaller step wins a tie, 2 and 4 both fit a file indented by 4s
+        .max_by_key(|(step, count)| (*count, usize::MAX - step))
+        .map(|(step, _)| Detected::Spaces(step))
+}
+
+/** whether a new line after `before` goes one level deeper */
+fn opens_block(before: &str, language: Option<&str>) -> bool {
+    match before.trim_end().chars().last() {
+        Some('{' | '[' | '(') => true,
+        Some(':') => language == Some("python"),
+        _ => false,
+    }
+}
+
+fn closes(opener: char, text: &str) -> bool {
+    matches!(
+        (opener, text.chars().next()),
+        ('{', Some('}')) | ('[', Some(']')) | ('(', Some(')'))
+    )
+}
+
+impl TextInput {
+    /** lines the selection touches, just the cursor line without one */
+    pub fn selected_line_span(&self) -> Range<usize> {
+        if !self.is_selecting || (self.selected_lines.is_empty() && !self.selected_lines_reversed) {
+            return self.focused_line..self.focused_line + 1;
+        }
+        if !self.selected_lines_reversed {
+            self.selected_lines.clone()
+        } else {
+            (self.selected_lines.end - 1)..(self.selected_lines.start + 1)
+        }
+    }
+
+    /** settings plus detection, after the content was loaded */
+    pub fn detect_indent_style(&mut self, settings: &Settings) {
+        self.indent_style = IndentStyle::for_buffer(&self.content, settings);
+    }
+
+    /**
+     * splits the line at `range` keeping the indentation, one level deeper after an opening
+     * bracket (or `:` in python). `{|}` puts the closing bracket on its own line
+     */
+    pub fn split_line_indented(&mut self, range: Range<usize>) {
+        let line = self.content[self.focused_line].clone();
+        let base = leading_whitespace(&line).to_string();
+        let before = &line[..range.start];
+        let after = line[range.end..].trim_start_matches([' ', '\t']);
+        let removed_ws = line.len() - range.end - after.len();
+
+        let language = self.syntax.as_ref().map(|syntax| syntax.language_name);
+        let deeper = opens_block(before, language);
+        let indent = if deeper { base.clone() + &self.indent_style.unit() } else { base.clone() };
+        let opener = before.trim_end().chars().last().unwrap_or(' ');
+
+        let mut new_lines = vec![];
+        if deeper && closes(opener, after) {
+            new_lines.push(indent.clone());
+            new_lines.push(base + after);
+        } else {
+            new_lines.push(indent.clone() + after);
+        }
+
+        let edit = self.begin_edit(
+            BufferPoint::new(self.focused_line, range.start),
+            BufferPoint::new(self.focused_line, range.end + removed_ws),
+        );
+        let first = self.focused_line;
+        self.content[first] = before.to_string().into();
+        for (i, new_line) in new_lines.iter().enumerate() {
+            self.content.insert(first + 1 + i, new_line.clone().into());
+        }
+        self.lines += new_lines.len();
+        let last = first + new_lines.len();
+        let end_col = self.content[last].len() - after.len();
+        self.finish_edit(edit, BufferPoint::new(last, end_col));
+
+        self.focused_line = first + 1;
+        self.selected_range = indent.len()..indent.len();
+        self.cursor_pos = indent.len();
+    }
+
+    /** typing a closing bracket as the first thing on a line takes one level off */
+    pub fn electric_dedent(&mut self, typed: &str) {
+        if !matches!(typed, "}" | "]" | ")") {
+            return;
+        }
+        let line = self.content[self.focused_line].clone();
+        let cursor = self.cursor_offset();
+        if cursor < typed.len() || !line[..cursor - typed.len()].chars().all(|c| c == ' ' || c == '\t') {
+            return;
+        }
+        let remove = self.indent_style.outdent_len(&line);
+        if remove == 0 {
+            return;
+        }
+        self.edit_line(self.focused_line, 0..remove, "");
+        self.selected_range = cursor - remove..cursor - remove;
+        self.cursor_pos = cursor - remove;
+    }
+
+    /** tab, indents every selected line or inserts up to the next indent stop */
+    pub fn indent(&mut self, _: &Indent, cx: &mut ViewContext<Self>) {
+        if self.command_line.is_some() || self.search_open || self.refuse_edit(cx) {
+            return;
+        }
+        let lines = self.selected_line_span();
+        if lines.len() > 1 {
+            self.shift_lines(lines, true, cx);
+            return;
+        }
+        let text = if self.indent_style.insert_spaces {
+            let line = &self.content[self.focused_line];
+            let column = column_at(line, self.selected_range.start, self.indent_style.tab_size);
+            " ".repeat(self.indent_style.indent_width - column % self.indent_style.indent_width)
+        } else {
+            "\t".to_string()
+        };
+        self.replace_text_in_range(None, &text, cx);
+    }
+
+    /** shift-tab, always works on whole lines */
+    pub fn outdent(&mut self, _: &Outdent, cx: &mut ViewContext<Self>) {
+        if self.command_line.is_some() || self.search_open || self.refuse_edit(cx) {
+            return;
+        }
+        let lines = self.selected_line_span();
+        self.shift_lines(lines, false, cx);
+    }
+
+    fn shift_lines(&mut self, lines: Range<usize>, indent: bool, cx: &mut ViewContext<Self>) {
+        self.dirty = true;
+        let unit = self.indent_style.unit();
+        let mut deltas = vec![];
+        self.begin_transaction();
+        for line in lines.clone() {
+            let delta = if indent {
+                if self.content[line].trim().is_empty() {
+                    0
+                } else {
+                    self.edit_line(line, 0..0, &unit);
+                    unit.len() as isize
+                }
+            } else {
+                let remove = self.indent_style.outdent_len(&self.content[line]);
+                self.edit_line(line, 0..remove, "");
+                -(remove as isize)
+            };
+            deltas.push(delta);
+        }
+        self.end_transaction();
+
+        // keep the selection on the same text, a selection from column 0 takes the new indent in
+        let shift = |pos: usize, delta: isize| {
+            if pos == 0 && delta > 0 {
+                0
+            } else {
+                (pos as isize + delta).max(0) as usize
+            }
+        };
+        let (top, bottom) = (deltas[0], deltas[deltas.len() - 1]);
+        // which end of selected_range sits on the top line, same rule as prepaint
+        let top_is_start = self.selected_lines_reversed == self.selection_reversed || lines.len() == 1;
+        let (start_delta, end_delta) = if top_is_start { (top, bottom) } else { (bottom, top) };
+        self.selected_range = shift(self.selected_range.start, start_delta)..shift(self.selected_range.end, end_delta);
+        self.cursor_pos = self.cursor_offset();
+        cx.notify();
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    cell::RefCell,
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    mem,
+};
+
+use gpui::{Hsla, Pixels, SharedString, TextRun, WindowContext};
+
+use super::wrap::DisplayLine;
+
+/** the hash only picks the bucket, a hit compares everything so a collision can't paint the wrong line */
+#[derive(Clone)]
+struct LayoutKey {
+    text: SharedString,
+    runs: Vec<TextRun>,
+    font_size: Pixels,
+    wrap_width: Option<Pixels>,
+}
+
+impl PartialEq for LayoutKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+            && self.font_size == other.font_size
+            && self.wrap_width == other.wrap_width
+            && self.runs == other.runs
+    }
+}
+
+impl Eq for LayoutKey {}
+
+impl Hash for LayoutKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.text.hash(state);
+        state.write_u64(style_hash(&self.runs, self.font_size, self.wrap_width));
+    }
+}
+
+/**
+ * shaped lines by content, so scrolling, cursor moves and edits elsewhere reuse them and an
+ * edited line simply stops being asked for. entries unused for a whole frame are dropped
+ */
+#[derive(Default)]
+pub struct LayoutCache {
+    previous_frame: RefCell<HashMap<LayoutKey, DisplayLine>>,
+    current_frame: RefCell<HashMap<LayoutKey, DisplayLine>>,
+}
+
+impl LayoutCache {
+    pub fn layout(
+        &self,
+        text: SharedString,
+        runs: &[TextRun],
+        font_size: Pixels,
+        wrap_width: Option<Pixels>,
+        cx: &WindowContext,
+    ) -> DisplayLine {
+        let key = LayoutKey {
+            text,
+            runs: runs.to_vec(),
+            font_size,
+            wrap_width,
+        };
+        if let Some(line) = self.current_frame.borrow().get(&key) {
+            return line.clone();
+        }
+        let line = match self.previous_frame.borrow_mut().remove(&key) {
+            Some(line) => line,
+            None => DisplayLine::shape(key.text.clone(), runs, font_size, wrap_width, cx),
+        };
+        self.current_frame.borrow_mut().insert(key, line.clone());
+        line
+    }
+
+    /** called once per render, whatever the last frame didn't ask for again goes */
+    pub fn finish_frame(&self) {
+        let current = mem::take(&mut *self.current_frame.borrow_mut());
+        *self.previous_frame.borrow_mut() = current;
+    }
+}
+
+fn hash_color(color: Hsla, state: &mut impl Hasher) {
+    for component in [color.h, color.s, color.l, color.a] {
+        component.to_bits().hash(state);
+    }
+}
+
+// colors and underlines are baked into the shaped line, so they are part of the key
+fn style_hash(runs: &[TextRun], font_size: Pixels, wrap_width: Option<Pixels>) -> u64 {
+    let mut state = DefaultHasher::new();
+    font_size.0.to_bits().hash(&mut state);
+    wrap_width.map(|width| width.0.to_bits()).hash(&mut state);
+    for run in runs {
+        run.len.hash(&mut state);
+        run.font.hash(&mut state);
+        hash_color(run.color, &mut state);
+        if let Some(background) = run.background_color {
+            hash_color(background, &mut state);
+        }
+        if let Some(underline) = &run.underline {
+            underline.thickness.0.to_bits().hash(&mut state);
+            underline.wavy.hash(&mut state);
+            if let Some(color) = underline.color {
+                hash_color(color, &mut state);
+            }
+        }
+        if let Some(strikethrough) = &run.strikethrough {
+            strikethrough.thickness.0.to_bits().hash(&mut state);
+            if let Some(color) = strikethrough.color {
+                hash_color(color, &mut state);
+            }
+        }
+    }
+    state.finish()
+}
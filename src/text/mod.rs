@@ -1,3 +1,18 @@
 pub mod text_input;
 pub mod text_render;
 pub mod text;
+pub mod command;
+pub mod disk;
+pub mod edit;
+pub mod gutter;
+pub mod scroll;
+pub mod wrap;
+pub mod layout_cache;
+pub mod column;
+pub mod word;
+pub mod indent;
+pub mod autopair;
+pub mod brackets;
+pub mod search;
+pub mod replace;
+pub mod undo;
@@ -0,0 +1,249 @@
+use gpui::{actions, ViewContext};
+use regex::{Captures, Regex};
+
+use super::{
+    edit::BufferPoint,
+    search::{before, build_regex, joined_text, point_at, Search, SearchMatch},
+    text::TextInput,
+};
+
+actions!(
+    replace,
+    [
+        OpenReplace,
+        SwitchSearchField,
+        ReplaceNext,
+        ReplaceAll,
+        TogglePreserveCase,
+        ToggleReplaceInSelection,
+    ]
+);
+
+/** FOO -> BAR, Foo -> Bar, anything else is left as typed */
+pub fn preserve_case(matched: &str, replacement: &str) -> String {
+    let letters: Vec<char> = matched.chars().filter(|c| c.is_alphabetic()).collect();
+    if letters.len() > 1 && letters.iter().all(|c| c.is_uppercase()) {
+        return replacement.to_uppercase();
+    }
+    if letters.first().is_some_and(|c| c.is_uppercase()) {
+        let mut chars = replacement.chars();
+        return match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        };
+    }
+    replacement.to_string()
+}
+
+/** `$1` and `${name}` in regex mode, the replacement as is otherwise */
+fn expand(search: &Search, captures: &Captures) -> String {
+    let replacement = search.replacement.as_deref().unwrap_or_default();
+    let mut expanded = String::new();
+    if search.regex {
+        captures.expand(replacement, &mut expanded);
+    } else {
+        expanded.push_str(replacement);
+    }
+    if search.preserve_case {
+        expanded = preserve_case(&captures[0], &expanded);
+    }
+    expanded
+}
+
+fn in_scope(search: &Search, found: SearchMatch) -> bool {
+    match search.scope {
+        Some((start, end)) => !before(found.start, start) && !before(end, found.end),
+        None => true,
+    }
+}
+
+/** where `point`, at or after `old_end`, is once old_end became `new_end` */
+fn shift_point(point: BufferPoint, old_end: BufferPoint, new_end: BufferPoint) -> BufferPoint {
+    if point.line == old_end.line {
+        BufferPoint::new(new_end.line, new_end.col + point.col - old_end.col)
+    } else {
+        BufferPoint::new(point.line + new_end.line - old_end.line, point.col)
+    }
+}
+
+impl TextInput {
+    /** both ends of the selection, top first */
+    fn selection_points(&self) -> (BufferPoint, BufferPoint) {
+        let lines = self.selected_line_span();
+        // same rule as prepaint for which end of selected_range is on the top line
+        let top_is_start = self.selected_lines_reversed == self.selection_reversed || lines.len() == 1;
+        let (top, bottom) = if top_is_start {
+            (self.selected_range.start, self.selected_range.end)
+        } else {
+            (self.selected_range.end, self.selected_range.start)
+        };
+        (BufferPoint::new(lines.start, top), BufferPoint::new(lines.end - 1, bottom))
+    }
+
+    pub fn open_replace(&mut self, _: &OpenReplace, cx: &mut ViewContext<Self>) {
+        // a multi line selection is where to replace, not what to find
+        let multi_line = self.selected_line_span().len() > 1;
+        let scope = multi_line.then(|| self.selection_points());
+        if !self.search_open || self.search.is_none() {
+            self.open_search_bar(false, cx);
+        }
+        if let Some(search) = self.search.as_mut() {
+            search.replacement.get_or_insert_with(String::new);
+            search.editing_replacement = !search.query.is_empty();
+            search.scope = scope;
+        }
+        cx.notify();
+    }
+
+    /** tab in the bar, between the find and the replace field */
+    pub fn switch_search_field(&mut self, _: &SwitchSearchField, cx: &mut ViewContext<Self>) {
+        if let Some(search) = self.search.as_mut().filter(|search| search.replacement.is_some()) {
+            search.editing_replacement = !search.editing_replacement;
+        }
+        cx.notify();
+    }
+
+    pub fn toggle_preserve_case(&mut self, _: &TogglePreserveCase, cx: &mut ViewContext<Self>) {
+        if let Some(search) = self.search.as_mut() {
+            search.preserve_case = !search.preserve_case;
+        }
+        cx.notify();
+    }
+
+    pub fn toggle_replace_in_selection(&mut self, _: &ToggleReplaceInSelection, cx: &mut ViewContext<Self>) {
+        let has_selection = self.is_selecting && !(self.selected_line_span().len() == 1 && self.selected_range.is_empty());
+        let selection = self.selection_points();
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        search.scope = match search.scope {
+            Some(_) => None,
+            None if has_selection => Some(selection),
+            None => {
+                self.status_message = Some("nothing selected".into());
+                None
+            }
+        };
+        cx.notify();
+    }
+
+    /** matches replace all would touch, the dry run shown in the bar */
+    pub fn replaceable_count(&self) -> usize {
+        let Some(search) = self.search.as_ref() else {
+            return 0;
+        };
+        search.matches.iter().filter(|found| in_scope(search, **found)).count()
+    }
+
+    fn replace_regex(&mut self) -> Option<Regex> {
+        let search = self.search.as_ref()?;
+        search.replacement.as_ref()?;
+        match build_regex(&search.query, search.regex, search.case) {
+            Ok(regex) if !search.query.is_empty() => Some(regex),
+            Ok(_) => None,
+            Err(err) => {
+                self.status_message = Some(err.into());
+                None
+            }
+        }
+    }
+
+    /**
+     * replaces the current match when the cursor is on it, then moves to the next one. the
+     * first press on a match that isn't current only goes there, so you see what changes
+     */
+    pub fn replace_next(&mut self, _: &ReplaceNext, cx: &mut ViewContext<Self>) {
+        if self.refuse_edit(cx) {
+            return;
+        }
+        let Some(regex) = self.replace_regex() else {
+            return;
+        };
+        let cursor = self.cursor_point();
+        let search = self.search.as_ref().unwrap();
+        let current = search
+            .current
+            .and_then(|current| search.matches.get(current).copied())
+            .filter(|found| found.start == cursor && in_scope(search, *found));
+        let Some(found) = current else {
+            self.search_step(true, cx);
+            return;
+        };
+
+        let (text, starts) = joined_text(&self.content);
+        // the match is found again for its capture groups, the stored one may be stale
+        let offset = starts.get(found.start.line).map(|start| start + found.start.col).filter(|offset| *offset <= text.len());
+        let captures = offset.and_then(|offset| {
+            regex.captures_at(&text, offset).filter(|captures| captures.get(0).unwrap().start() == offset)
+        });
+        let Some(captures) = captures else {
+            self.search_step(true, cx);
+            return;
+        };
+        let replacement = expand(search, &captures);
+        let matched_end = point_at(&starts, captures.get(0).unwrap().end());
+
+        self.dirty = true;
+        let end = self.replace_range(found.start, matched_end, &replacement);
+        // searching again from after the replacement jumps to the next match, replacing goes down
+        if let Some(search) = self.search.as_mut() {
+            search.origin = end;
+            search.backwards = false;
+            // the end of the selection moves with the text in front of it
+            if let Some((_, scope_end)) = search.scope.as_mut() {
+                *scope_end = shift_point(*scope_end, matched_end, end);
+            }
+        }
+        self.jump_to_point(end, cx);
+        self.run_search(true, cx);
+    }
+
+    /** every match in scope, as one edit from the first match to the last */
+    pub fn replace_all(&mut self, _: &ReplaceAll, cx: &mut ViewContext<Self>) {
+        if self.refuse_edit(cx) {
+            return;
+        }
+        let Some(regex) = self.replace_regex() else {
+            return;
+        };
+        let search = self.search.as_ref().unwrap();
+        let (text, starts) = joined_text(&self.content);
+
+        let mut replaced = String::new();
+        let mut span: Option<(usize, usize)> = None;
+        let mut count = 0;
+        for captures in regex.captures_iter(&text) {
+            let found = captures.get(0).unwrap();
+            let point = SearchMatch {
+                start: point_at(&starts, found.start()),
+                end: point_at(&starts, found.end()),
+            };
+            if found.is_empty() || !in_scope(search, point) {
+                continue;
+            }
+            let (first, last) = span.unwrap_or((found.start(), found.start()));
+            replaced.push_str(&text[last..found.start()]);
+            replaced.push_str(&expand(search, &captures));
+            span = Some((first, found.end()));
+            count += 1;
+        }
+
+        let Some((first, last)) = span else {
+            self.status_message = Some(format!("pattern not found: {}", search.query).into());
+            cx.notify();
+            return;
+        };
+        self.dirty = true;
+        let start = point_at(&starts, first);
+        // one undo takes every replacement back
+        self.begin_transaction();
+        self.replace_range(start, point_at(&starts, last), &replaced);
+        self.end_transaction();
+        if let Some(search) = self.search.as_mut() {
+            search.scope = None;
+        }
+        self.search_open = false;
+        self.status_message = Some(format!("replaced {} matches", count).into());
+        self.jump_to_point(start, cx);
+    }
+}
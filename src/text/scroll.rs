@@ -0,0 +1,267 @@
+use std::{cmp::min, time::Duration};
+
+use gpui::{
+    actions, div, prelude::*, px, Div, Hsla, MouseButton, MouseDownEvent, Pixels, ScrollDelta,
+    ScrollWheelEvent, ViewContext,
+};
+
+use crate::{
+    settings::settings::{metrics, settings, LineMetrics},
+    theme::theme::Theme,
+};
+
+use super::text::TextInput;
+
+actions!(
+    scroll,
+    [
+        ScrollLineDown,
+        ScrollLineUp,
+        ScrollHalfPageDown,
+        ScrollHalfPageUp,
+        ScrollCursorCenter,
+        ScrollCursorTop,
+        ScrollCursorBottom,
+    ]
+);
+
+pub const SCROLLBAR_WIDTH: Pixels = px(12.);
+const WHEEL_LINES: f32 = 3.; // per notch of a mouse wheel, trackpads send pixels instead
+
+// the view scrolls on its own, the cursor only drags it along when the cursor moves
+impl TextInput {
+    /** whole lines that fit in the text area of the last render */
+    pub fn visible_lines(&self, metrics: LineMetrics) -> usize {
+        metrics.lines_in(self.text_height)
+    }
+
+    /** buffer line under a window y, clamped to the buffer */
+    pub fn line_at_y(&self, y: Pixels, metrics: LineMetrics) -> usize {
+        // wrapped lines are more than one row tall, the last paint knows where each line went
+        let painted = self.line_layouts.iter().find(|(_, (bounds, _))| bounds.top() <= y && y < bounds.bottom());
+        if let Some((line, _)) = painted {
+            return *line;
+        }
+        let line = (metrics.lines_for_y(y) + self.scroll_top).max(0.) as usize;
+        min(line, self.lines - 1)
+    }
+
+    /** jumps straight to `top`, also ends any smooth scroll in flight */
+    pub fn set_scroll(&mut self, top: f32, cx: &mut ViewContext<Self>) {
+        // the last line can go all the way up, like ctrl-e in vim
+        let top = top.clamp(0., self.lines.saturating_sub(1) as f32);
+        self.scroll_top = top;
+        self.scroll_target = top;
+        self.scroll_task = None;
+        cx.notify();
+    }
+
+    /** eases towards `top` when smooth scrolling is on */
+    pub fn scroll_to(&mut self, top: f32, cx: &mut ViewContext<Self>) {
+        if !settings(cx).smooth_scroll {
+            self.set_scroll(top, cx);
+            return;
+        }
+        self.scroll_target = top.clamp(0., self.lines.saturating_sub(1) as f32);
+        if self.scroll_task.is_some() {
+            return;
+        }
+
+        self.scroll_task = Some(cx.spawn(|this, mut cx| async move {
+            loop {
+                cx.background_executor().timer(Duration::from_millis(16)).await;
+                let Ok(done) = this.update(&mut cx, |this, cx| {
+                    let distance = this.scroll_target - this.scroll_top;
+                    if distance.abs() < 0.05 {
+                        this.scroll_top = this.scroll_target;
+                        this.scroll_task = None;
+                    } else {
+                        this.scroll_top += distance * 0.3;
+                    }
+                    cx.notify();
+                    this.scroll_task.is_none()
+                }) else {
+                    break;
+                };
+                if done {
+                    break;
+                }
+            }
+        }));
+    }
+
+    /** scrolls just enough to keep `scrolloff` lines around the cursor, called when it moves */
+    pub fn autoscroll(&mut self, metrics: LineMetrics, scrolloff: usize, cx: &mut ViewContext<Self>) {
+        let visible = self.visible_lines(metrics);
+        let margin = min(scrolloff, visible.saturating_sub(1) / 2);
+        // against the target, so an animation in flight isn't undone
+        let top = self.scroll_target.round() as usize;
+
+        if self.focused_line < top + margin {
+            self.set_scroll(self.focused_line.saturating_sub(margin) as f32, cx);
+            return;
+        }
+
+        // walk up from below the cursor until the screen is full, wrapped lines take several rows
+        let last = min(self.focused_line + margin, self.lines - 1);
+        let mut new_top = last + 1;
+        let mut rows = 0;
+        while new_top > top {
+            let line_rows = self.display_rows(new_top - 1, cx);
+            if rows + line_rows > visible && new_top <= self.focused_line {
+                break;
+            }
+            rows += line_rows;
+            new_top -= 1;
+        }
+        if new_top > top {
+            self.set_scroll(new_top as f32, cx);
+        }
+    }
+
+    /** after scrolling with ctrl-e/ctrl-y the cursor moves instead of leaving the screen */
+    fn keep_cursor_in_view(&mut self, cx: &mut ViewContext<Self>) {
+        let metrics = metrics(cx);
+        let visible = self.visible_lines(metrics);
+        let margin = min(settings(cx).scrolloff, visible.saturating_sub(1) / 2);
+        let top = self.scroll_target.round() as usize;
+        let first = min(top + margin, self.lines - 1);
+        let last = (top + visible).saturating_sub(margin + 1).max(first);
+
+        if self.focused_line < first {
+            self.move_to_line(first);
+        } else if self.focused_line > last {
+            self.move_to_line(min(last, self.lines - 1));
+        }
+        self.last_cursor = (self.focused_line, self.cursor_pos);
+    }
+
+    /** same as up/down, keeps the goal column */
+    fn move_to_line(&mut self, line: usize) {
+        let pos = self.offset_for_goal_column(line);
+        self.focused_line = line;
+        self.selected_range = pos..pos;
+        self.cursor_pos = pos;
+        self.selected_lines = 0..0;
+        self.selection_reversed = false;
+        self.marked_range = None;
+        self.is_selecting = false;
+    }
+
+    pub fn on_scroll_wheel(&mut self, event: &ScrollWheelEvent, cx: &mut ViewContext<Self>) {
+        match event.delta {
+            ScrollDelta::Lines(delta) => self.scroll_to(self.scroll_target - delta.y * WHEEL_LINES, cx),
+            // trackpads are smooth already
+            ScrollDelta::Pixels(delta) => {
+                let lines = metrics(cx).lines_for_y(delta.y);
+                self.set_scroll(self.scroll_top - lines, cx);
+            }
+        }
+    }
+
+    pub fn scroll_line_down(&mut self, _: &ScrollLineDown, cx: &mut ViewContext<Self>) {
+        self.scroll_to(self.scroll_target + 1., cx);
+        self.keep_cursor_in_view(cx);
+    }
+
+    pub fn scroll_line_up(&mut self, _: &ScrollLineUp, cx: &mut ViewContext<Self>) {
+        self.scroll_to(self.scroll_target - 1., cx);
+        self.keep_cursor_in_view(cx);
+    }
+
+    /** ctrl-d, the cursor moves the same number of lines as the view */
+    pub fn scroll_half_page_down(&mut self, _: &ScrollHalfPageDown, cx: &mut ViewContext<Self>) {
+        let half = (self.visible_lines(metrics(cx)) / 2).max(1);
+        self.move_to_line(min(self.focused_line + half, self.lines - 1));
+        self.scroll_to(self.scroll_target + half as f32, cx);
+        self.keep_cursor_in_view(cx);
+    }
+
+    pub fn scroll_half_page_up(&mut self, _: &ScrollHalfPageUp, cx: &mut ViewContext<Self>) {
+        let half = (self.visible_lines(metrics(cx)) / 2).max(1);
+        self.move_to_line(self.focused_line.saturating_sub(half));
+        self.scroll_to(self.scroll_target - half as f32, cx);
+        self.keep_cursor_in_view(cx);
+    }
+
+    /** zz */
+    pub fn scroll_cursor_center(&mut self, _: &ScrollCursorCenter, cx: &mut ViewContext<Self>) {
+        let half = self.visible_lines(metrics(cx)) / 2;
+        self.scroll_to(self.focused_line.saturating_sub(half) as f32, cx);
+    }
+
+    /** zt, scrolloff lines stay above the cursor */
+    pub fn scroll_cursor_top(&mut self, _: &ScrollCursorTop, cx: &mut ViewContext<Self>) {
+        let margin = settings(cx).scrolloff;
+        self.scroll_to(self.focused_line.saturating_sub(margin) as f32, cx);
+    }
+
+    /** zb */
+    pub fn scroll_cursor_bottom(&mut self, _: &ScrollCursorBottom, cx: &mut ViewContext<Self>) {
+        let margin = settings(cx).scrolloff;
+        let visible = self.visible_lines(metrics(cx));
+        self.scroll_to((self.focused_line + margin + 1).saturating_sub(visible) as f32, cx);
+    }
+
+    /** lines to mark on the scrollbar track */
+    pub fn scrollbar_marks(&self, theme: &Theme) -> Vec<(usize, Hsla)> {
+        let mut marks: Vec<(usize, Hsla)> = self
+            .diagnostics
+            .keys()
+            .filter_map(|line| Some((*line, self.line_severity(*line)?.color(theme))))
+            .collect();
+        if let Some(search) = self.search.as_ref() {
+            let mut lines: Vec<usize> = search.matches.iter().map(|found| found.start.line).collect();
+            lines.dedup();
+            marks.extend(lines.into_iter().map(|line| (line, theme.search_match_current())));
+        }
+        marks.sort_by_key(|(line, _)| *line);
+        marks
+    }
+
+    pub fn render_scrollbar(&self, metrics: LineMetrics, theme: &Theme, cx: &mut ViewContext<Self>) -> Div {
+        let height = self.text_height;
+        let lines = self.lines.max(1) as f32;
+        let thumb_height = (height * (self.visible_lines(metrics) as f32 / lines)).max(px(16.)).min(height);
+        let thumb_top = (height - thumb_height) * (self.scroll_top / (lines - 1.).max(1.));
+
+        div()
+            .absolute()
+            .top_0()
+            .right_0()
+            .w(SCROLLBAR_WIDTH)
+            .h(height)
+            .bg(theme.gutter_background)
+            .on_mouse_down(
+                MouseButton::Left,
+                cx.listener(|this, event: &MouseDownEvent, cx| {
+                    this.scrollbar_drag = true;
+                    this.scrollbar_jump(event.position.y, cx);
+                    cx.stop_propagation();
+                }),
+            )
+            .child(
+                div()
+                    .absolute()
+                    .top(thumb_top)
+                    .w_full()
+                    .h(thumb_height)
+                    .bg(theme.selection),
+            )
+            .children(self.scrollbar_marks(theme).into_iter().map(|(line, color)| {
+                div()
+                    .absolute()
+                    .top(height * (line as f32 / lines))
+                    .w_full()
+                    .h(px(2.))
+                    .bg(color)
+            }))
+    }
+
+    /** centers the view on the part of the file under `y` on the track */
+    pub fn scrollbar_jump(&mut self, y: Pixels, cx: &mut ViewContext<Self>) {
+        let fraction = (y / self.text_height).clamp(0., 1.);
+        let half = self.visible_lines(metrics(cx)) as f32 / 2.;
+        self.set_scroll(fraction * self.lines as f32 - half, cx);
+    }
+}
@@ -0,0 +1,388 @@
+use std::ops::Range;
+
+use gpui::{actions, SharedString, ViewContext};
+use regex::{Regex, RegexBuilder};
+
+use super::{edit::BufferPoint, text::TextInput};
+
+actions!(
+    search,
+    [
+        OpenSearch,
+        OpenSearchBackwards,
+        CloseSearch,
+        SearchBackspace,
+        SearchNext,
+        SearchPrevious,
+        ToggleSearchRegex,
+        ToggleSearchCase,
+    ]
+);
+
+const MAX_MATCHES: usize = 100_000; // past this the count says "100000+"
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CaseMode {
+    #[default]
+    Smart, // sensitive once the query has an uppercase letter
+    Sensitive,
+    Insensitive,
+}
+
+/** a match, end is exclusive and on a later line when the pattern spans a \n */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub start: BufferPoint,
+    pub end: BufferPoint,
+}
+
+/** the last search, kept after the bar closes so n/N and the highlights still work */
+pub struct Search {
+    pub query: String,
+    pub regex: bool,
+    pub case: CaseMode,
+    pub backwards: bool, // opened with ?, next goes up
+    pub origin: BufferPoint, // cursor when the bar opened, typing searches from here
+    pub matches: Vec<SearchMatch>, // whole buffer, in order
+    pub current: Option<usize>,
+    pub error: Option<String>, // invalid regex
+    pub stale: bool, // the buffer changed since the matches were found
+    pub replacement: Option<String>, // Some while the replace field is open
+    pub editing_replacement: bool, // typing goes to the replace field
+    pub preserve_case: bool,
+    pub scope: Option<(BufferPoint, BufferPoint)>, // replace only inside the selection
+}
+
+pub fn build_regex(query: &str, regex: bool, case: CaseMode) -> Result<Regex, String> {
+    let pattern = if regex { query.to_string() } else { regex::escape(query) };
+    let insensitive = match case {
+        CaseMode::Smart => !query.chars().any(char::is_uppercase),
+        CaseMode::Sensitive => false,
+        CaseMode::Insensitive => true,
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(insensitive)
+        .multi_line(true)
+        .build()
+        .map_err(|err| err.to_string())
+}
+
+/** the lines joined with \n so a pattern can span them, and where each line starts */
+pub fn joined_text(lines: &[SharedString]) -> (String, Vec<usize>) {
+    let mut starts = Vec::with_capacity(lines.len());
+    let mut text = String::new();
+    for line in lines {
+        starts.push(text.len());
+        text.push_str(line);
+        text.push('\n');
+    }
+    text.pop();
+    (text, starts)
+}
+
+pub fn point_at(starts: &[usize], offset: usize) -> BufferPoint {
+    let line = starts.partition_point(|start| *start <= offset) - 1;
+    BufferPoint::new(line, offset - starts[line])
+}
+
+/** every non empty match */
+pub fn find_matches(lines: &[SharedString], regex: &Regex) -> Vec<SearchMatch> {
+    let (text, starts) = joined_text(lines);
+    regex
+        .find_iter(&text)
+        .filter(|found| !found.is_empty())
+        .take(MAX_MATCHES)
+        .map(|found| SearchMatch {
+            start: point_at(&starts, found.start()),
+            end: point_at(&starts, found.end()),
+        })
+        .collect()
+}
+
+pub fn before(a: BufferPoint, b: BufferPoint) -> bool {
+    (a.line, a.col) < (b.line, b.col)
+}
+
+impl TextInput {
+    pub fn cursor_point(&self) -> BufferPoint {
+        BufferPoint::new(self.focused_line, self.cursor_offset())
+    }
+
+    pub fn open_search_bar(&mut self, backwards: bool, cx: &mut ViewContext<Self>) {
+        // a selection on one line is what you want to find, usually
+        let selected = if self.selected_line_span().len() == 1 && !self.selected_range.is_empty() {
+            self.content[self.focused_line][self.selected_range.clone()].to_string()
+        } else {
+            String::new()
+        };
+        let (regex, case, preserve_case) = self
+            .search
+            .as_ref()
+            .map(|search| (search.regex, search.case, search.preserve_case))
+            .unwrap_or_default();
+        self.search = Some(Search {
+            query: selected,
+            regex,
+            case,
+            backwards,
+            origin: self.cursor_point(),
+            matches: vec![],
+            current: None,
+            error: None,
+            stale: false,
+            replacement: None,
+            editing_replacement: false,
+            preserve_case,
+            scope: None,
+        });
+        self.search_open = true;
+        self.command_line = None;
+        self.run_search(true, cx);
+        cx.notify();
+    }
+
+    pub fn open_search(&mut self, _: &OpenSearch, cx: &mut ViewContext<Self>) {
+        self.open_search_bar(false, cx);
+    }
+
+    pub fn open_search_backwards(&mut self, _: &OpenSearchBackwards, cx: &mut ViewContext<Self>) {
+        self.open_search_bar(true, cx);
+    }
+
+    /** the matches stay highlighted, :noh clears them */
+    pub fn close_search(&mut self, _: &CloseSearch, cx: &mut ViewContext<Self>) {
+        self.search_open = false;
+        cx.notify();
+    }
+
+    pub fn search_input(&mut self, text: &str, cx: &mut ViewContext<Self>) {
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        match (search.editing_replacement, search.replacement.as_mut()) {
+            (true, Some(replacement)) => replacement.push_str(text),
+            _ => {
+                search.query.push_str(text);
+                self.run_search(true, cx);
+            }
+        }
+        cx.notify();
+    }
+
+    pub fn search_backspace(&mut self, _: &SearchBackspace, cx: &mut ViewContext<Self>) {
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        if let (true, Some(replacement)) = (search.editing_replacement, search.replacement.as_mut()) {
+            replacement.pop();
+        } else if search.query.pop().is_none() {
+            // like the command line, backspace on nothing leaves
+            self.search_open = false;
+        } else {
+            self.run_search(true, cx);
+        }
+        cx.notify();
+    }
+
+    pub fn toggle_search_regex(&mut self, _: &ToggleSearchRegex, cx: &mut ViewContext<Self>) {
+        if let Some(search) = self.search.as_mut() {
+            search.regex = !search.regex;
+        }
+        self.run_search(true, cx);
+        cx.notify();
+    }
+
+    pub fn toggle_search_case(&mut self, _: &ToggleSearchCase, cx: &mut ViewContext<Self>) {
+        if let Some(search) = self.search.as_mut() {
+            search.case = match search.case {
+                CaseMode::Smart => CaseMode::Sensitive,
+                CaseMode::Sensitive => CaseMode::Insensitive,
+                CaseMode::Insensitive => CaseMode::Smart,
+            };
+        }
+        self.run_search(true, cx);
+        cx.notify();
+    }
+
+    /**
+     * finds the matches off the ui thread, a newer search drops the task of an older one.
+     * `jump` moves the cursor to the first match from where the search started
+     */
+    pub fn run_search(&mut self, jump: bool, cx: &mut ViewContext<Self>) {
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        search.stale = false;
+        self.search_task = None;
+        if search.query.is_empty() {
+            search.matches.clear();
+            search.current = None;
+            search.error = None;
+            return;
+        }
+        let regex = match build_regex(&search.query, search.regex, search.case) {
+            Ok(regex) => regex,
+            Err(err) => {
+                search.error = Some(err);
+                search.matches.clear();
+                search.current = None;
+                return;
+            }
+        };
+        search.error = None;
+
+        let lines = self.content.clone();
+        self.search_task = Some(cx.spawn(|this, mut cx| async move {
+            let matches = cx
+                .background_executor()
+                .spawn(async move { find_matches(&lines, &regex) })
+                .await;
+            let _ = this.update(&mut cx, |this, cx| {
+                this.search_finished(matches, jump, cx);
+            });
+        }));
+    }
+
+    fn search_finished(&mut self, matches: Vec<SearchMatch>, jump: bool, cx: &mut ViewContext<Self>) {
+        let cursor = self.cursor_point();
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        search.matches = matches;
+        if jump {
+            let origin = search.origin;
+            search.current = if search.backwards {
+                search.matches.iter().rposition(|found| !before(origin, found.start))
+            } else {
+                search.matches.iter().position(|found| !before(found.start, origin))
+            }
+            .or((!search.matches.is_empty()).then_some(0));
+            // the buffer may have changed while the search ran
+            if let Some(found) = search.current.map(|current| search.matches[current]) {
+                self.jump_to_point(self.clamp_point(found.start), cx);
+            }
+        } else {
+            search.current = search.matches.iter().position(|found| found.start == cursor);
+        }
+        cx.notify();
+    }
+
+    pub fn jump_to_point(&mut self, point: BufferPoint, cx: &mut ViewContext<Self>) {
+        self.focused_line = point.line;
+        self.selected_lines = 0..0;
+        self.selection_reversed = false;
+        self.marked_range = None;
+        self.move_to(point.col, cx);
+    }
+
+    /** n/N, forwards is relative to the search direction like vim */
+    pub fn search_step(&mut self, forwards: bool, cx: &mut ViewContext<Self>) {
+        let cursor = self.cursor_point();
+        let Some(search) = self.search.as_mut() else {
+            self.status_message = Some("no previous search".into());
+            cx.notify();
+            return;
+        };
+        if search.matches.is_empty() {
+            self.status_message = Some(format!("pattern not found: {}", search.query).into());
+            cx.notify();
+            return;
+        }
+        let down = forwards != search.backwards;
+        let (next, wrapped) = if down {
+            match search.matches.iter().position(|found| before(cursor, found.start)) {
+                Some(ix) => (ix, false),
+                None => (0, true),
+            }
+        } else {
+            match search.matches.iter().rposition(|found| before(found.start, cursor)) {
+                Some(ix) => (ix, false),
+                None => (search.matches.len() - 1, true),
+            }
+        };
+        search.current = Some(next);
+        let found = search.matches[next];
+        self.status_message = Some(match (wrapped, down) {
+            (true, true) => "search hit BOTTOM, continuing at TOP".into(),
+            (true, false) => "search hit TOP, continuing at BOTTOM".into(),
+            _ => self.search_status(),
+        });
+        // stale matches can point past the end of lines that got shorter
+        self.jump_to_point(self.clamp_point(found.start), cx);
+    }
+
+    pub fn search_next(&mut self, _: &SearchNext, cx: &mut ViewContext<Self>) {
+        self.search_step(true, cx);
+    }
+
+    pub fn search_previous(&mut self, _: &SearchPrevious, cx: &mut ViewContext<Self>) {
+        self.search_step(false, cx);
+    }
+
+    /** `/query [.*] [Aa]  3 of 17`, for the status line */
+    pub fn search_status(&self) -> SharedString {
+        let Some(search) = self.search.as_ref() else {
+            return SharedString::default();
+        };
+        let mut status = format!("{}{}", if search.backwards { '?' } else { '/' }, search.query);
+        if search.regex {
+            status += "  [.*]";
+        }
+        match search.case {
+            CaseMode::Smart => {}
+            CaseMode::Sensitive => status += "  [Aa]",
+            CaseMode::Insensitive => status += "  [aa]",
+        }
+        let count = match search.matches.len() {
+            MAX_MATCHES => format!("{}+", MAX_MATCHES),
+            count => count.to_string(),
+        };
+        if let Some(replacement) = &search.replacement {
+            status += &format!("  replace: {}", replacement);
+            if search.preserve_case {
+                status += "  [AB]";
+            }
+            if search.scope.is_some() {
+                status += "  [in selection]";
+            }
+        }
+        if let Some(err) = &search.error {
+            status += &format!("  {}", err.lines().last().unwrap_or_default());
+        } else if !search.query.is_empty() && search.matches.is_empty() {
+            status += "  no matches";
+        } else if let Some(current) = search.current {
+            status += &format!("  {} of {}", current + 1, count);
+        } else if !search.query.is_empty() {
+            status += &format!("  {} matches", count);
+        }
+        // what replace all would do, before doing it
+        if search.replacement.is_some() && search.error.is_none() && !search.query.is_empty() {
+            status += &format!(", {} to replace", self.replaceable_count());
+        }
+        status.into()
+    }
+
+    /** the parts of matches on `line`, true for the current one */
+    pub fn search_ranges_on(&self, line: usize) -> Vec<(Range<usize>, bool)> {
+        let Some(search) = self.search.as_ref() else {
+            return vec![];
+        };
+        let text = &self.content[line];
+        let first = search.matches.partition_point(|found| found.end.line < line);
+        search.matches[first..]
+            .iter()
+            .enumerate()
+            .take_while(|(_, found)| found.start.line <= line)
+            .filter_map(|(ix, found)| {
+                let start = if found.start.line == line { found.start.col } else { 0 };
+                let end = if found.end.line == line { found.end.col } else { text.len() };
+                // matches from before an edit until the search reruns
+                let end = end.min(text.len());
+                if start >= end || !text.is_char_boundary(start) || !text.is_char_boundary(end) {
+                    return None;
+                }
+                Some((start..end, search.current == Some(first + ix)))
+            })
+            .collect()
+    }
+}
@@ -1,16 +1,33 @@
-use std::{collections::HashMap, ops::Range};
+use std::{collections::HashMap, ops::Range, sync::Arc};
 
 use gpui::{
-    prelude::*, AppContext, Bounds, FocusHandle, FocusableView, Pixels, SharedString, View, WrappedLine,
+    prelude::*, px, AppContext, Bounds, FocusHandle, FocusableView, Pixels, SharedString, Task, View,
 };
 
-use crate::lsp::decode::Diagnostics;
+use super::{
+    edit::BufferPoint, indent::IndentStyle, layout_cache::LayoutCache, search::Search, undo::History, wrap::DisplayLine,
+};
+
+use crate::{
+    files::{
+        files::{LoadProgress, LoadedFile, ReadOnly},
+        format::FileFormat,
+        watcher::DiskStamp,
+    },
+    lsp::{decode::Diagnostics, document::LspDocument},
+    settings::settings::{Settings, SoftWrap},
+    syntax::syntax::{HighlightSpan, Syntax},
+};
 
 // defines what is basically the list of lines that is a file
 pub struct TextInput {
     pub focus_handle: FocusHandle,
     pub focused_line: usize,
     pub cursor_pos: usize, // cursor l-r, scolling pos not current, "ideal" not actual
+    pub indent_style: IndentStyle, // tab size etc, detected from the file on load
+    pub auto_closed: Vec<usize>, // closers autopair inserted on auto_closed_line, typing them steps over
+    pub auto_closed_line: usize,
+    pub goal_column: Option<(usize, usize, usize)>, // line, offset, display column of the last up/down
     pub lines: usize,
     pub open_file: String,
     pub content: Vec<SharedString>,
@@ -20,11 +37,105 @@ pub struct TextInput {
     pub selected_range: Range<usize>, // end..botton + full_lines + 0..top 
     pub selection_reversed: bool,
     pub marked_range: Option<Range<usize>>,
-    pub last_layout: Option<WrappedLine>,
-    pub last_bounds: Option<Bounds<Pixels>>,
+    pub line_layouts: HashMap<usize, (Bounds<Pixels>, DisplayLine)>, // painted lines of the last frame
+    pub layout_cache: LayoutCache,
+    pub soft_wrap: Option<SoftWrap>, // None follows the filetype/global setting
+    pub wrap_width: Option<Pixels>, // of the last render, None when not wrapping
     pub last_cursor_scroll: Pixels, // l-r content offset
     pub is_selecting: bool,
     pub diagnostics: HashMap<usize, Vec<Diagnostics>>,
+    pub write_backup: bool, // keep file~ on save
+    pub status_message: Option<SharedString>, // shown in the status line, errors etc
+    pub format: FileFormat, // line endings etc to save with
+    pub command_line: Option<String>, // `:` command being typed, None when closed
+    pub read_only: Option<ReadOnly>, // edits and saves are refused while set
+    pub dirty: bool, // edited since the last load/save
+    pub disk_stamp: Option<DiskStamp>, // file on disk when we last read/wrote it
+    pub watch_task: Option<Task<()>>,
+    pub loading: Option<Arc<LoadProgress>>, // Some until the background load finishes
+    pub load_task: Option<Task<()>>,
+    pub syntax: Option<Syntax>, // None for files we have no grammar for
+    pub highlights: HashMap<usize, Vec<HighlightSpan>>, // visible lines only, rebuilt every render
+    pub scroll_top: f32, // first visible line, fractional while smooth scrolling
+    pub scroll_target: f32, // where a smooth scroll is heading, same as scroll_top otherwise
+    pub scroll_task: Option<Task<()>>,
+    pub text_height: Pixels, // text area of the last render, for page sizes
+    pub last_cursor: (usize, usize), // line + pos at the last render, autoscroll when it moves
+    pub scrollbar_drag: bool,
+    pub gutter_drag_anchor: Option<usize>, // line the gutter drag started on
+    pub bracket_match: Option<(BufferPoint, BufferPoint)>, // around the cursor
+    pub bracket_match_at: Option<BufferPoint>, // cursor `bracket_match` was found for, None after edits
+    pub bracket_depths: Vec<usize>, // nesting depth at each line start, the known prefix after edits
+    pub search: Option<Search>, // the last search, highlighted until :noh
+    pub search_open: bool, // typing goes to the search bar
+    pub search_task: Option<Task<()>>,
+    pub pending_jump: Option<BufferPoint>, // where to put the cursor once loading finishes
+    pub history: History, // undo and redo
+    pub lsp: Option<LspDocument>, // open on the language's server, None until it started
+    pub lsp_task: Option<Task<()>>,
+}
+
+impl TextInput {
+    pub fn new(focus_handle: FocusHandle, open_file: String, loaded: LoadedFile, settings: &Settings) -> Self {
+        let mut syntax = Syntax::for_path(&open_file);
+        if let Some(syntax) = syntax.as_mut() {
+            syntax.parse(&loaded.lines);
+        }
+        TextInput {
+            focus_handle,
+            focused_line: 0,
+            cursor_pos: 0,
+            indent_style: IndentStyle::from_settings(settings),
+            auto_closed: vec![],
+            auto_closed_line: 0,
+            goal_column: None,
+            disk_stamp: DiskStamp::read(&open_file),
+            open_file,
+            lines: loaded.lines.len(),
+            content: loaded.lines,
+            placeholder: "".into(),
+            selected_lines: 0..0,
+            selected_lines_reversed: false,
+            selected_range: 0..0,
+            selection_reversed: false,
+            marked_range: None,
+            line_layouts: HashMap::new(),
+            layout_cache: LayoutCache::default(),
+            soft_wrap: None,
+            wrap_width: None,
+            last_cursor_scroll: px(0.0),
+            is_selecting: false,
+            diagnostics: HashMap::new(),
+            write_backup: settings.backup,
+            status_message: loaded.error.clone().map(Into::into),
+            format: loaded.format,
+            command_line: None,
+            read_only: loaded.read_only,
+            dirty: false,
+            watch_task: None,
+            loading: None,
+            load_task: None,
+            syntax,
+            highlights: HashMap::new(),
+            scroll_top: 0.,
+            scroll_target: 0.,
+            scroll_task: None,
+            text_height: px(0.),
+            last_cursor: (0, 0),
+            scrollbar_drag: false,
+            gutter_drag_anchor: None,
+            bracket_match: None,
+            bracket_match_at: None,
+            bracket_depths: vec![],
+            search: None,
+            search_open: false,
+            search_task: None,
+            pending_jump: None,
+            history: History::default(),
+            lsp: None,
+            lsp_task: None,
+        }
+    }
 }
 
 // one line of a file
@@ -1,13 +1,13 @@
 use std::{cmp::{max, min}, mem::swap, ops::Range};
 
 use gpui::{
-    actions, point, px, Bounds, ClipboardItem, MouseDownEvent, MouseMoveEvent, MouseUpEvent, Pixels, Point, UTF16Selection, ViewContext, ViewInputHandler
+    actions, point, Bounds, ClipboardItem, MouseDownEvent, MouseMoveEvent, MouseUpEvent, Pixels, Point, UTF16Selection, ViewContext, ViewInputHandler
 };
 use unicode_segmentation::*;
 
-use crate::files::files::save;
+use crate::settings::settings::metrics;
 
-use super::text::TextInput;
+use super::{edit::BufferPoint, text::TextInput};
 
 actions!(
     text_input,
@@ -36,51 +36,46 @@ actions!(
 
 impl TextInput {
     pub fn enter(&mut self, _: &Enter, cx: &mut ViewContext<Self>) {
-        let range = self.selected_text_range(false, cx).unwrap().range;
-
-        self.content.insert(
-            self.focused_line + 1,
-            self.content[self.focused_line][range.end..]
-                .to_owned()
-                .into(),
-        );
-        self.content[self.focused_line] = self.content[self.focused_line][0..range.start]
-            .to_owned()
-            .into();
-
-        self.focused_line += 1;
-        self.lines += 1;
-        // self.cursor_pos = 0;
-
-        self.selected_range = 0..0;
+        if self.refuse_edit(cx) {
+            return;
+        }
+        self.dirty = true;
+        let range = self.selected_range.clone();
+        self.split_line_indented(range);
+        self.is_selecting = false;
+        self.selected_lines = 0..0;
+        cx.notify();
     }
-    pub fn save(&mut self, _: &Save, _cx: &mut ViewContext<Self>) {
-        println!("saved");
-        save(self.open_file.clone(),
-            self.content.clone(),
-        );
+    pub fn save(&mut self, _: &Save, cx: &mut ViewContext<Self>) {
+        self.write_file(false, cx);
     }
-    pub fn down(&mut self, _: &Down, _cx: &mut ViewContext<Self>) {
-        self.focused_line = min(self.lines - 1, self.focused_line + 1);
-        let pos = min(self.content[self.focused_line].len(), self.cursor_pos);
-        self.selected_range = pos..pos; // doesn't affect cursor_pos
+    pub fn down(&mut self, _: &Down, cx: &mut ViewContext<Self>) {
+        if self.move_display_row(true, cx) {
+            return;
+        }
+        let line = min(self.lines - 1, self.focused_line + 1);
+        let pos = self.offset_for_goal_column(line);
+        self.focused_line = line;
+        self.selected_range = pos..pos;
+        self.cursor_pos = pos;
         self.selected_lines = 0..0;
         self.selection_reversed = false;
         self.marked_range = None;
-        self.last_layout = None;
-        self.last_bounds = None;
         self.is_selecting = false;
     }
-    pub fn up(&mut self, _: &Up, _cx: &mut ViewContext<Self>) {
+    pub fn up(&mut self, _: &Up, cx: &mut ViewContext<Self>) {
+        if self.move_display_row(false, cx) {
+            return;
+        }
         // usize would overflow
-        self.focused_line = max(0 as i32, self.focused_line as i32 - 1) as usize;
-        let pos = min(self.content[self.focused_line].len(), self.cursor_pos);
-        self.selected_range = pos..pos; // doesn't affect cursor_pos
+        let line = max(0 as i32, self.focused_line as i32 - 1) as usize;
+        let pos = self.offset_for_goal_column(line);
+        self.focused_line = line;
+        self.selected_range = pos..pos;
+        self.cursor_pos = pos;
         self.selected_lines = 0..0;
         self.selection_reversed = false;
         self.marked_range = None;
-        self.last_layout = None;
-        self.last_bounds = None;
         self.is_selecting = false;
     }
     pub fn left(&mut self, _: &Left, cx: &mut ViewContext<Self>) {
@@ -126,39 +121,37 @@ impl TextInput {
         self.is_selecting = true;
     }
 
-    pub fn select_up(&mut self, _: &SelectUp, _cx: &mut ViewContext<Self>) {
-        if !self.is_selecting {
-            // don't set sel range start because it's same
-            self.selected_lines.start = self.focused_line;
-        }
-        self.focused_line = max(0 as i32, self.focused_line as i32 - 1) as usize;
-        let pos = min(self.content[self.focused_line].len(), self.cursor_pos);
-        if !self.selection_reversed {
-            self.selected_range.end = pos;
-        }else {
-            self.selected_range.start = pos;
-        }
-        self.selected_lines.end = self.focused_line + 1;
-        self.is_selecting = true;
-        self.selected_lines_reversed = self.selected_lines.start >= self.selected_lines.end;
-        if self.selected_range.end < self.selected_range.start {
-            self.selection_reversed = !self.selection_reversed;
-            self.selected_range = self.selected_range.end..self.selected_range.start;
-        }
+    pub fn select_up(&mut self, _: &SelectUp, cx: &mut ViewContext<Self>) {
+        // within a wrapped line first, like up
+        let (line, pos) = self.display_row_target(false, cx).unwrap_or_else(|| {
+            let line = self.focused_line.saturating_sub(1);
+            (line, self.offset_for_goal_column(line))
+        });
+        self.extend_selection_to(line, pos);
     }
 
-    pub fn select_down(&mut self, _: &SelectDown, _cx: &mut ViewContext<Self>) {
+    pub fn select_down(&mut self, _: &SelectDown, cx: &mut ViewContext<Self>) {
+        let (line, pos) = self.display_row_target(true, cx).unwrap_or_else(|| {
+            let line = min(self.lines - 1, self.focused_line + 1);
+            (line, self.offset_for_goal_column(line))
+        });
+        self.extend_selection_to(line, pos);
+    }
+
+    /** moves the cursor end of the selection to `pos` on `line`, the other end stays */
+    pub fn extend_selection_to(&mut self, line: usize, pos: usize) {
         if !self.is_selecting {
+            // don't set sel range start because it's same
             self.selected_lines.start = self.focused_line;
         }
-        self.focused_line = min(self.lines - 1, self.focused_line + 1);
-        let pos = min(self.content[self.focused_line].len(), self.cursor_pos);
+        self.focused_line = line;
+        self.cursor_pos = pos;
         if !self.selection_reversed {
             self.selected_range.end = pos;
         }else {
             self.selected_range.start = pos;
         }
-        self.selected_lines.end = self.focused_line + 1; 
+        self.selected_lines.end = self.focused_line + 1;
         self.is_selecting = true;
         self.selected_lines_reversed = self.selected_lines.start >= self.selected_lines.end;
         if self.selected_range.end < self.selected_range.start {
@@ -181,7 +174,19 @@ impl TextInput {
     }
 
     pub fn backspace(&mut self, _: &Backspace, cx: &mut ViewContext<Self>) {
+        if self.refuse_edit(cx) {
+            return;
+        }
+        self.dirty = true;
+        if !self.is_selecting && self.delete_empty_pair() {
+            cx.notify();
+            return;
+        }
         if !self.is_selecting && self.focused_line != 0 && self.selected_range.start == 0 {
+            let edit = self.begin_edit(
+                BufferPoint::new(self.focused_line - 1, self.content[self.focused_line - 1].len()),
+                BufferPoint::new(self.focused_line, 0),
+            );
             self.focused_line -= 1;
             // append line to above line
             self.content[self.focused_line] = (self.content[self.focused_line].to_string()
@@ -193,6 +198,8 @@ impl TextInput {
             self.cursor_pos = self.content[self.focused_line].len();
             self.selected_range = self.cursor_pos..self.cursor_pos;
             self.lines -= 1;
+            self.finish_edit(edit, edit.start);
+            self.auto_closed.clear();
             return;
         }
 
@@ -215,6 +222,16 @@ impl TextInput {
             };
             println!("{:?}", selected_lines);
 
+        let (first, last) = (selected_lines.start, selected_lines.end.saturating_sub(1).max(selected_lines.start));
+        let edit = if first == last {
+            self.begin_edit(
+                BufferPoint::new(first, min(range.start, range.end)),
+                BufferPoint::new(first, max(range.start, range.end)),
+            )
+        } else {
+            self.begin_edit(BufferPoint::new(first, range.start), BufferPoint::new(last, range.end))
+        };
+
         let mut lines_to_merge = vec![]; // lines to wrap because deleted '\n'
 
         for line in selected_lines.clone() {
@@ -253,6 +270,13 @@ impl TextInput {
             self.content.remove(line - i); // adjust for already taken
             self.lines -= 1;
         }
+        self.finish_edit(edit, edit.start);
+        // closers autopair put after the deleted text moved back with it
+        if first == last {
+            self.shift_auto_closed(range.clone(), 0);
+        } else {
+            self.auto_closed.clear();
+        }
 
         cx.notify();
 
@@ -271,7 +295,7 @@ impl TextInput {
     }
 
     pub fn on_mouse_down(&mut self, event: &MouseDownEvent, cx: &mut ViewContext<Self>) {
-        self.focused_line = (event.position.y / px(30.0)) as usize;
+        self.focused_line = self.line_at_y(event.position.y, metrics(cx));
         
         self.is_selecting = true;
 
@@ -284,9 +308,18 @@ impl TextInput {
 
     pub fn on_mouse_up(&mut self, _: &MouseUpEvent, _: &mut ViewContext<Self>) {
         self.is_selecting = false;
+        self.gutter_drag_anchor = None;
+        self.scrollbar_drag = false;
     }
 
     pub fn on_mouse_move(&mut self, event: &MouseMoveEvent, cx: &mut ViewContext<Self>) {
+        if self.scrollbar_drag {
+            self.scrollbar_jump(event.position.y, cx);
+            return;
+        }
+        if self.gutter_drag(event.position.y, metrics(cx), cx) {
+            return;
+        }
         if self.is_selecting {
             self.select_to(self.index_for_mouse_position(event.position), cx);
         }
@@ -360,8 +393,7 @@ impl TextInput {
             return 0;
         }
 
-        let (Some(bounds), Some(line)) = (self.last_bounds.as_ref(), self.last_layout.as_ref())
-        else {
+        let Some((bounds, line)) = self.line_layouts.get(&self.focused_line) else {
             return 0;
         };
         if position.y < bounds.top() {
@@ -370,8 +402,7 @@ impl TextInput {
         if position.y > bounds.bottom() {
             return self.content[self.focused_line].len();
         }
-        line.unwrapped_layout
-            .closest_index_for_x(position.x - bounds.left())
+        line.index_for_position(position - bounds.origin)
     }
 
     pub fn select_to(&mut self, offset: usize, cx: &mut ViewContext<Self>) {
@@ -447,13 +478,29 @@ impl TextInput {
             .unwrap_or(self.content[self.focused_line].len())
     }
 
+    /** read only buffers (undecodable files) can't be touched, says why in the status line */
+    pub fn refuse_edit(&mut self, cx: &mut ViewContext<Self>) -> bool {
+        if self.loading.is_some() {
+            // render shows the progress instead
+            return true;
+        }
+        if self.read_only.is_none() {
+            return false;
+        }
+        self.status_message = Some(format!(
+            "{} is read only, it would not save back as {}",
+            self.open_file,
+            self.format.encoding.name()
+        ).into());
+        cx.notify();
+        true
+    }
+
     pub fn reset(&mut self) {
         self.content[self.focused_line] = "".into();
         self.selected_range = 0..0;
         self.selection_reversed = false;
         self.marked_range = None;
-        self.last_layout = None;
-        self.last_bounds = None;
         self.is_selecting = false;
     }
 }
@@ -497,19 +544,49 @@ impl ViewInputHandler for TextInput {
         new_text: &str,
         cx: &mut ViewContext<Self>,
     ) {
+        if self.search_open {
+            self.search_input(new_text, cx);
+            return;
+        }
+        if let Some(command) = self.command_line.as_mut() {
+            command.push_str(new_text);
+            cx.notify();
+            return;
+        }
+        if self.refuse_edit(cx) {
+            return;
+        }
+        self.dirty = true;
+
         let range = range_utf16
             .as_ref()
             .map(|range_utf16| self.range_from_utf16(range_utf16))
             .or(self.marked_range.clone())
             .unwrap_or(self.selected_range.clone());
 
+        // wrapping a selection and electric dedent make more than one edit
+        self.begin_transaction();
+        if range_utf16.is_none() && self.marked_range.is_none() && self.autopair(range.clone(), new_text) {
+            self.end_transaction();
+            cx.notify();
+            return;
+        }
+
+        let edit = self.begin_edit(
+            BufferPoint::new(self.focused_line, range.start),
+            BufferPoint::new(self.focused_line, range.end),
+        );
         self.content[self.focused_line] = (self.content[self.focused_line][0..range.start]
             .to_owned()
             + new_text
             + &self.content[self.focused_line][range.end..])
             .into();
+        self.finish_edit(edit, BufferPoint::new(self.focused_line, range.start + new_text.len()));
+        self.shift_auto_closed(range.clone(), new_text.len());
         self.selected_range = range.start + new_text.len()..range.start + new_text.len();
         self.marked_range.take();
+        self.electric_dedent(new_text);
+        self.end_transaction();
         cx.notify();
     }
 
@@ -520,17 +597,36 @@ impl ViewInputHandler for TextInput {
         new_selected_range_utf16: Option<Range<usize>>,
         cx: &mut ViewContext<Self>,
     ) {
+        if self.search_open {
+            self.search_input(new_text, cx);
+            return;
+        }
+        if let Some(command) = self.command_line.as_mut() {
+            command.push_str(new_text);
+            cx.notify();
+            return;
+        }
+        if self.refuse_edit(cx) {
+            return;
+        }
+        self.dirty = true;
+
         let range = range_utf16
             .as_ref()
             .map(|range_utf16| self.range_from_utf16(range_utf16))
             .or(self.marked_range.clone())
             .unwrap_or(self.selected_range.clone());
 
+        let edit = self.begin_edit(
+            BufferPoint::new(self.focused_line, range.start),
+            BufferPoint::new(self.focused_line, range.end),
+        );
         self.content[self.focused_line] = (self.content[self.focused_line][0..range.start]
             .to_owned()
             + new_text
             + &self.content[self.focused_line][range.end..])
             .into();
+        self.finish_edit(edit, BufferPoint::new(self.focused_line, range.start + new_text.len()));
         self.marked_range = Some(range.start..range.start + new_text.len());
         self.selected_range = new_selected_range_utf16
             .as_ref()
@@ -547,17 +643,15 @@ impl ViewInputHandler for TextInput {
         bounds: Bounds<Pixels>,
         _cx: &mut ViewContext<Self>,
     ) -> Option<Bounds<Pixels>> {
-        let last_layout = self.last_layout.as_ref()?;
+        let (_, line) = self.line_layouts.get(&self.focused_line)?;
         let range = self.range_from_utf16(&range_utf16);
+        // ime popups only need a rough spot, the row the range starts on
+        let start = line.position_for_index(range.start);
+        let end = line.position_for_index(range.end);
+        let end_x = if end.y == start.y { end.x } else { start.x };
         Some(Bounds::from_corners(
-            point(
-                bounds.left() + last_layout.unwrapped_layout.x_for_index(range.start),
-                bounds.top(),
-            ),
-            point(
-                bounds.left() + last_layout.unwrapped_layout.x_for_index(range.end),
-                bounds.bottom(),
-            ),
+            point(bounds.left() + start.x, bounds.top() + start.y),
+            point(bounds.left() + end_x, bounds.top() + start.y + line.line_height),
         ))
     }
 }
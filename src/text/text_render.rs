@@ -1,54 +1,89 @@
 use std::{
     cmp::{max, min},
+    collections::HashMap,
     mem::swap,
     ops::Range,
 };
 
 use gpui::{
-    div, fill, hsla, point, prelude::*, px, relative, rgb, rgba, size, white, Bounds, CursorStyle,
+    div, fill, point, prelude::*, px, relative, size, Bounds, CursorStyle,
     ElementId, ElementInputHandler, FocusableView, GlobalElementId, LayoutId, MouseButton,
-    PaintQuad, Pixels, Point, SharedString, Style, TextRun, UnderlineStyle, ViewContext,
-    WindowContext, WrappedLine,
+    PaintQuad, Pixels, SharedString, StrikethroughStyle, Style, TextRun, UnderlineStyle, ViewContext,
+    WindowContext,
 };
 
-use super::text::{TextElement, TextInput};
+use crate::{
+    lsp::decode::{Diagnostics, Severity},
+    settings::settings::{settings, zoom, ResetZoom, ZoomIn, ZoomOut},
+    syntax::syntax::HighlightSpan,
+    theme::theme::{theme, Theme},
+};
+
+use super::{
+    edit::BufferPoint,
+    gutter::GutterLayout,
+    wrap::DisplayLine,
+    scroll::SCROLLBAR_WIDTH,
+    command::{CloseCommandLine, CommandBackspace, OpenCommandLine, RunCommand},
+    text::{TextElement, TextInput},
+};
+
+pub const STATUS_LINE_HEIGHT: Pixels = px(24.);
 
 impl Render for TextInput {
     fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
-        let bounds: usize =
-            (((cx.viewport_size().height.to_f64() / 30.) as f32 + 0.5).round() / 2.0) as usize;
-
-        // visible lines
-        let mut min_line = max(self.focused_line as i32 - bounds as i32, 0) as usize;
-        let mut max_line = min(self.focused_line + bounds, self.lines);
-
-        if min_line == 0 && max_line + min_line < bounds * 2 {
-            // no bounds because if max_line + min_line < bounds, never overflow
-            max_line += bounds * 2 - (max_line + min_line);
-        } else if max_line == self.lines {
-            min_line = max_line.checked_sub(bounds * 2).unwrap_or(0);
+        let settings = settings(cx).clone();
+        let metrics = settings.metrics();
+        self.text_height = cx.viewport_size().height - STATUS_LINE_HEIGHT;
+
+        let char_width = Self::char_width(cx);
+        let gutter = GutterLayout::new(self.lines, char_width, settings.line_numbers);
+        let text_width = cx.viewport_size().width - gutter.width() - SCROLLBAR_WIDTH;
+        let wrap_width = self.wrap_width_for(text_width, char_width, cx);
+        self.wrap_width = wrap_width;
+        self.line_layouts.clear();
+        self.layout_cache.finish_frame();
+        if self.search.as_ref().is_some_and(|search| search.stale) {
+            self.run_search(false, cx);
+        }
+
+        // the view only follows the cursor when the cursor moved, wheel scrolling leaves it behind
+        let cursor = (self.focused_line, self.cursor_pos);
+        if cursor != self.last_cursor {
+            self.last_cursor = cursor;
+            self.autoscroll(metrics, settings.scrolloff, cx);
         }
 
-        // add one for clamp and make sure not oob
-        max_line = min(max_line + 1, self.lines);
-        // checked sub checks oob for min_line
+        // visible lines, one extra for the partly shown line at the bottom
+        let min_line = min(self.scroll_top.floor() as usize, self.lines - 1);
+        let max_line = min(min_line + self.visible_lines(metrics) + 2, self.lines);
+        let scroll_offset = -metrics.y_for_lines(self.scroll_top.fract());
+
+        let theme = theme(cx);
+        self.highlights = match self.syntax.as_ref() {
+            Some(syntax) => syntax.highlights(&self.content, min_line..max_line, |scope| theme.syntax_color(scope)),
+            None => HashMap::new(),
+        };
+        if settings.rainbow_brackets {
+            self.add_rainbow_highlights(min_line..max_line, &theme);
+        }
+        // the scan can cover thousands of lines, only redone when the cursor or the text changes
+        let cursor = BufferPoint::new(self.focused_line, self.cursor_offset());
+        if self.bracket_match_at != Some(cursor) {
+            self.bracket_match = self.matching_bracket();
+            self.bracket_match_at = Some(cursor);
+        }
 
-        let wrap_width = None; // cx.viewport_size().width;
         let cursor_push_dist = px(40.0); // dist from side of screen to move the screen
 
-        let cursor_pos = get_cursor_pos_for_line(
-            self.cursor_offset(),
-            wrap_width,
-            cx,
-            self.content[self.focused_line].clone(),
-        );
+        let cursor_pos = self.layout_line(self.focused_line, cx).position_for_index(self.cursor_offset()).x;
 
         let mut cursor_push_offset = // How far to actually move
-            -max(px(0.0), cursor_pos - cx.viewport_size().width + cursor_push_dist);
+            -max(px(0.0), cursor_pos - text_width + cursor_push_dist);
 
         // if we are going back, don't scroll until we just barely get back
-        if (cursor_pos - cx.viewport_size().width) + self.last_cursor_scroll
-            < -cx.viewport_size().width + cursor_push_dist
+        if (cursor_pos - text_width) + self.last_cursor_scroll
+            < -text_width + cursor_push_dist
         {
             // this took me so long. The idea is that the offset = pos + distance from 40x on the left side
             cursor_push_offset = -max(px(0.0), cursor_pos - cursor_push_dist);
@@ -58,12 +93,29 @@ impl Render for TextInput {
             cursor_push_offset = self.last_cursor_scroll;
         }
 
+        if wrap_width.is_some() {
+            // everything fits, no sideways scrolling
+            cursor_push_offset = px(0.);
+        }
+
         // update the last cursor scroll to new
         self.last_cursor_scroll = cursor_push_offset;
 
+        let rows: Vec<_> = (min_line..max_line)
+            .map(|i| (i, self.render_gutter_cell(i, gutter, settings.line_numbers, &theme, cx)))
+            .collect();
+
         div()
             .flex()
-            .key_context("TextInput")
+            .relative()
+            .size_full()
+            .key_context(if self.command_line.is_some() {
+                "CommandLine"
+            } else if self.search_open {
+                "SearchBar"
+            } else {
+                "TextInput"
+            })
             .track_focus(&self.focus_handle(cx))
             .cursor(CursorStyle::IBeam)
             .on_action(cx.listener(Self::enter))
@@ -85,63 +137,251 @@ impl Render for TextInput {
             .on_action(cx.listener(Self::cut))
             .on_action(cx.listener(Self::copy))
             .on_action(cx.listener(Self::save))
+            .on_action(cx.listener(Self::open_command_line))
+            .on_action(cx.listener(Self::run_command))
+            .on_action(cx.listener(Self::close_command_line))
+            .on_action(cx.listener(Self::command_backspace))
             .on_mouse_down(MouseButton::Left, cx.listener(Self::on_mouse_down))
             .on_mouse_up(MouseButton::Left, cx.listener(Self::on_mouse_up))
             .on_mouse_up_out(MouseButton::Left, cx.listener(Self::on_mouse_up))
             .on_mouse_move(cx.listener(Self::on_mouse_move))
-            .bg(rgb(0xeeeeee))
-            .line_height(px(30.))
-            .text_size(px(24.))
-            .child(div().flex_col().children((min_line..max_line).map(|i| {
+            .on_scroll_wheel(cx.listener(Self::on_scroll_wheel))
+            .on_action(cx.listener(Self::scroll_line_down))
+            .on_action(cx.listener(Self::scroll_line_up))
+            .on_action(cx.listener(Self::scroll_half_page_down))
+            .on_action(cx.listener(Self::scroll_half_page_up))
+            .on_action(cx.listener(Self::scroll_cursor_center))
+            .on_action(cx.listener(Self::scroll_cursor_top))
+            .on_action(cx.listener(Self::scroll_cursor_bottom))
+            .on_action(cx.listener(Self::toggle_soft_wrap))
+            .on_action(cx.listener(Self::word_left))
+            .on_action(cx.listener(Self::word_right))
+            .on_action(cx.listener(Self::select_word_left))
+            .on_action(cx.listener(Self::select_word_right))
+            .on_action(cx.listener(Self::delete_word_left))
+            .on_action(cx.listener(Self::delete_word_right))
+            .on_action(cx.listener(Self::indent))
+            .on_action(cx.listener(Self::outdent))
+            .on_action(cx.listener(Self::jump_to_bracket))
+            .on_action(cx.listener(Self::open_search))
+            .on_action(cx.listener(Self::open_search_backwards))
+            .on_action(cx.listener(Self::close_search))
+            .on_action(cx.listener(Self::search_backspace))
+            .on_action(cx.listener(Self::search_next))
+            .on_action(cx.listener(Self::search_previous))
+            .on_action(cx.listener(Self::toggle_search_regex))
+            .on_action(cx.listener(Self::toggle_search_case))
+            .on_action(cx.listener(Self::open_replace))
+            .on_action(cx.listener(Self::switch_search_field))
+            .on_action(cx.listener(Self::replace_next))
+            .on_action(cx.listener(Self::replace_all))
+            .on_action(cx.listener(Self::toggle_preserve_case))
+            .on_action(cx.listener(Self::toggle_replace_in_selection))
+            .on_action(cx.listener(Self::undo))
+            .on_action(cx.listener(Self::redo))
+            .on_action(cx.listener(Self::open_project_search))
+            .bg(theme.background)
+            .text_color(theme.foreground)
+            .on_action(cx.listener(|_, _: &ZoomIn, cx| zoom(1., cx)))
+            .on_action(cx.listener(|_, _: &ZoomOut, cx| zoom(-1., cx)))
+            .on_action(cx.listener(|_, _: &ResetZoom, cx| zoom(0., cx)))
+            .font_family(settings.font_family())
+            .font_weight(settings.font_weight())
+            .line_height(metrics.line_height)
+            .text_size(metrics.font_size)
+            .child(div().flex_col().w_full().top(scroll_offset).children(rows.into_iter().map(|(i, gutter_cell)| {
                 div()
-                    .flex_col()
+                    .flex()
+                    .flex_row()
                     .w_full()
-                    .left(cursor_push_offset)
-                    .bg(white())
-                    .child(TextElement {
-                        input: cx.view().clone(),
-                        lines_pixels: px(30.),
-                        id: i,
-                        wrap: wrap_width, // px num
-                    })
+                    .child(gutter_cell)
+                    .child(
+                        // only the text scrolls sideways, the gutter stays put
+                        div().flex_grow().overflow_hidden().child(
+                            div()
+                                .flex_col()
+                                .w_full()
+                                .left(cursor_push_offset)
+                                .bg(theme.background)
+                                .child(TextElement {
+                                    input: cx.view().clone(),
+                                    lines_pixels: metrics.line_height,
+                                    id: i,
+                                    wrap: wrap_width, // px num
+                                }),
+                        ),
+                    )
             })))
+            .child(self.render_scrollbar(metrics, &theme, cx))
+    }
+}
+
+impl TextInput {
+    pub fn render_status_line(&self, theme: &Theme) -> impl IntoElement {
+        let message: SharedString = match (&self.command_line, &self.loading) {
+            (Some(command), _) => format!(":{}", command).into(),
+            (None, _) if self.search_open => self.search_status(),
+            (None, Some(progress)) => format!("loading {}... {}%", self.open_file, progress.percent()).into(),
+            (None, None) => self.status_message.clone().or_else(|| self.diagnostic_status()).unwrap_or_default(),
+        };
+        let mut format = format!("{} {}", self.format.encoding.name().to_lowercase(), self.format.line_ending);
+        if self.format.bom {
+            format += " [bom]";
+        }
+        if !self.format.final_newline {
+            format += " [noeol]";
+        }
+        if self.read_only.is_some() {
+            format += " [RO]";
+        }
+        if self.dirty {
+            format += " [+]";
+        }
+
+        div()
+            .flex()
+            .justify_between()
+            .px_2()
+            .bg(theme.status_bar_background)
+            .text_color(theme.status_bar_foreground)
+            .h(STATUS_LINE_HEIGHT)
+            .text_size(px(14.))
+            .child(message)
+            .child(format!("{}:{}  {}", self.focused_line + 1, self.cursor_pos + 1, format))
+    }
+}
+
+/**
+ * splits the line into runs at every highlight, marked text and diagnostic boundary so they
+ * can overlap, the innermost highlight picks the color and marked text wins the underline
+ */
+fn build_runs(
+    text: &str,
+    base: &TextRun,
+    theme: &Theme,
+    highlights: &[HighlightSpan],
+    marked_range: Option<Range<usize>>,
+    diagnostics: &[Diagnostics],
+) -> Vec<TextRun> {
+    let len = text.len();
+    let mut boundaries = vec![0, len];
+    boundaries.extend(highlights.iter().flat_map(|span| [span.range.start, span.range.end]));
+    boundaries.extend(marked_range.iter().flat_map(|range| [range.start, range.end]));
+    boundaries.extend(
+        diagnostics
+            .iter()
+            .flat_map(|diagnostic| [diagnostic.diagnostic_range.start, diagnostic.diagnostic_range.end]),
+    );
+    // runs have to end on chars, lsp ranges and stale highlights might not
+    let mut boundaries: Vec<usize> = boundaries
+        .into_iter()
+        .map(|offset| min(offset, len))
+        .filter(|offset| text.is_char_boundary(*offset))
+        .collect();
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    let mut runs = vec![];
+    for segment in boundaries.windows(2) {
+        let (start, end) = (segment[0], segment[1]);
+        let covers = |range: &Range<usize>| range.start <= start && end <= range.end;
+
+        let mut color = base.color;
+        let mut innermost = None;
+        for span in highlights.iter().filter(|span| covers(&span.range)) {
+            if innermost.map_or(true, |innermost_start| span.range.start > innermost_start) {
+                innermost = Some(span.range.start);
+                color = span.color;
+            }
+        }
+
+        let covering: Vec<&Diagnostics> = diagnostics.iter().filter(|part| covers(&part.diagnostic_range)).collect();
+        // unused code is faded rather than underlined, the worst of the rest picks the underline
+        if covering.iter().any(|part| part.diagnostic.unnecessary) {
+            color = color.opacity(0.5);
+        }
+        let worst = covering
+            .iter()
+            .map(|part| &part.diagnostic)
+            .filter(|diagnostic| !(diagnostic.severity == Severity::Hint && (diagnostic.unnecessary || diagnostic.deprecated)))
+            .map(|diagnostic| diagnostic.severity)
+            .min();
+        let underline = if marked_range.as_ref().is_some_and(covers) {
+            Some(UnderlineStyle {
+                color: Some(color),
+                thickness: px(1.0),
+                wavy: false,
+            })
+        } else if let Some(severity) = worst {
+            Some(UnderlineStyle {
+                color: Some(severity.color(theme)),
+                thickness: px(if severity == Severity::Hint { 1.0 } else { 2.0 }),
+                wavy: severity != Severity::Hint,
+            })
+        } else {
+            None
+        };
+        let strikethrough = covering.iter().any(|part| part.diagnostic.deprecated).then(|| StrikethroughStyle {
+            color: Some(color),
+            thickness: px(1.0),
+        });
+
+        runs.push(TextRun {
+            len: end - start,
+            color,
+            underline,
+            strikethrough,
+            ..base.clone()
+        });
     }
+    runs
 }
 
 pub struct PrepaintState {
-    pub lines: Option<smallvec::SmallVec<[WrappedLine; 1]>>,
+    pub line: Option<DisplayLine>,
     pub cursor: Option<PaintQuad>,
-    pub selection: Option<PaintQuad>,
+    pub selection: Vec<PaintQuad>, // a quad per wrapped row
+    pub brackets: Vec<PaintQuad>, // the matched pair, if either half is on this line
+    pub search: Vec<PaintQuad>, // search matches, the current one stronger
 }
 
-fn get_cursor_pos_for_line(
-    cursor_offset: usize,
-    wrap_width: Option<Pixels>,
-    cx: &mut WindowContext,
-    input_line: SharedString,
-) -> Pixels {
-    let run = TextRun {
-        len: input_line.len(),
-        font: cx.text_style().font(),
-        color: gpui::blue(),
-        background_color: None,
-        underline: None,
-        strikethrough: None,
-    };
-
-    let line = cx
-        .text_system()
-        .shape_text(input_line, px(24.), &vec![run], wrap_width)
-        .unwrap();
-
-    let cursor_pos = line[0]
-        .position_for_index(cursor_offset, cx.line_height())
-        .unwrap_or(Point {
-            x: px(0.),
-            y: px(0.),
-        });
+impl TextElement {
+    /** the line's text, or the placeholder, split into highlight runs */
+    fn styled_line(&self, input: &TextInput, cx: &WindowContext) -> (SharedString, Vec<TextRun>) {
+        let content = &input.content[self.id];
+        let (display_text, text_color) = if content.is_empty() {
+            (input.placeholder.clone(), theme(cx).foreground.opacity(0.2))
+        } else {
+            (content.clone(), cx.text_style().color)
+        };
 
-    cursor_pos.x
+        let settings = settings(cx);
+        let run = TextRun {
+            len: display_text.len(),
+            font: settings.font(),
+            color: text_color,
+            background_color: None,
+            underline: None,
+            strikethrough: None,
+        };
+        let (highlights, diagnostics) = if content.is_empty() {
+            (&[][..], &[][..])
+        } else {
+            (
+                input.highlights.get(&self.id).map(Vec::as_slice).unwrap_or(&[]),
+                input.diagnostics.get(&self.id).map(Vec::as_slice).unwrap_or(&[]),
+            )
+        };
+        let runs = build_runs(
+            &display_text,
+            &run,
+            &theme(cx),
+            highlights,
+            input.marked_range.clone().filter(|_| input.focused_line == self.id),
+            diagnostics,
+        );
+        (display_text, runs)
+    }
 }
 
 impl Element for TextElement {
@@ -159,28 +399,15 @@ impl Element for TextElement {
         cx: &mut WindowContext,
     ) -> (LayoutId, Self::RequestLayoutState) {
         let mut style = Style::default();
-        let t_style = cx.text_style();
 
         style.size.width = relative(1.).into();
 
+        // same text and runs as prepaint, so prepaint gets it from the cache
         let input = self.input.read(cx);
-        let content = input.content[self.id].clone();
-
-        let run = TextRun {
-            len: content.len(),
-            font: t_style.font(),
-            color: t_style.color,
-            background_color: None,
-            underline: None,
-            strikethrough: None,
-        };
-        let font_size = px(24.);
-        let line = cx
-            .text_system()
-            .shape_text(content, font_size, &vec![run], self.wrap)
-            .unwrap();
-
-        style.size.height = line[0].size(cx.line_height()).height.into();
+        let (text, runs) = self.styled_line(input, cx);
+        let font_size = settings(cx).metrics().font_size;
+        let line = input.layout_cache.layout(text, &runs, font_size, self.wrap, cx);
+        style.size.height = line.height().into();
 
         (cx.request_layout(style, []), ())
     }
@@ -226,150 +453,58 @@ impl Element for TextElement {
             selected_range = 0..0;
         }
         let cursor = input.cursor_offset();
-        let style = cx.text_style();
-
-        let (display_text, text_color) = if content.is_empty() {
-            (input.placeholder.clone(), hsla(0., 0., 0., 0.2))
-        } else {
-            (content.clone(), style.color)
-        };
-
-        let run = TextRun {
-            len: display_text.len(),
-            font: style.font(),
-            color: text_color,
-            background_color: None,
-            underline: None,
-            strikethrough: None,
-        };
-        let runs = if let Some(marked_range) = input.marked_range.as_ref() {
-            vec![
-                TextRun {
-                    len: marked_range.start,
-                    ..run.clone()
-                },
-                TextRun {
-                    len: marked_range.end - marked_range.start,
-                    underline: Some(UnderlineStyle {
-                        color: Some(run.color),
-                        thickness: px(1.0),
-                        wavy: false,
-                    }),
-                    ..run.clone()
-                },
-                TextRun {
-                    len: display_text.len() - marked_range.end,
-                    ..run.clone()
-                },
-            ]
-            .into_iter()
-            .filter(|run| run.len > 0)
-            .collect()
-        } else if input.diagnostics.contains_key(&self.id) {
-            let mut diagnostics = input.diagnostics.get(&self.id).unwrap().clone();
-            diagnostics.sort_by_key(|r| r.diagnostic_range.start);
-
-            let mut runs = vec![];
-
-            let mut cursor_pos = 0;
-            for diagnostic in diagnostics {
-                println!("FOUND: {:?}", diagnostic.diagnostic_range);
-
-                runs.append(&mut vec![TextRun {
-                    len: diagnostic.diagnostic_range.start,
-                    ..run.clone()
-                }]);
-                runs.append(&mut vec![
-                    // TextRun {
-                    //     len: diagnostic.diagnostic_range.start,
-                    //     ..run.clone()
-                    // },
-                    TextRun {
-                        len: diagnostic.diagnostic_range.end - diagnostic.diagnostic_range.start,
-                        underline: Some(UnderlineStyle {
-                            color: Some(hsla(0.15, 1.0, 0.5, 1.0)),
-                            thickness: px(2.0),
-                            wavy: true,
-                        }),
-                        ..run.clone()
-                    },
-                    // TextRun {
-                    //     len: display_text.len() - diagnostic.diagnostic_range.end,
-                    //     ..run.clone()
-                    // },
-                ]);
-                cursor_pos = diagnostic.diagnostic_range.end;
-
-                // now need to insert regular text in the gaps
-                // display_text.len(), when buffer, split len in 2 parts at ..start and end..
-
-                // fn fill_gaps(runs: &Vec<TextRun>, len: usize) {
-                //     let vec_gaps: Vec<Range<usize>> = vec![];
-                //     let cursor = 0;
-
-                //     for run in runs {
-                //         if run.
-
-                //     }
-                // }
-
-                // fill_gaps(&runs, display_text.len())
-            }
-            runs
-        } else {
-            vec![run]
-        };
+        let (display_text, runs) = self.styled_line(input, cx);
         // println!("{}", display_text);
 
-        let font_size = style.font_size.to_pixels(cx.rem_size());
-        let line = cx
-            .text_system()
-            .shape_text(display_text, font_size, &runs, self.wrap)
-            .unwrap();
+        let font_size = settings(cx).metrics().font_size;
+        let line = input.layout_cache.layout(display_text, &runs, font_size, self.wrap, cx);
 
-        let cursor_pos = line[0]
-            .position_for_index(cursor, cx.line_height())
-            .unwrap_or(Point {
-                x: px(0.),
-                y: px(0.),
-            });
+        let cursor_pos = line.position_for_index(cursor);
 
-        let mut selection = None;
+        let theme = theme(cx);
+        let mut selection = vec![];
         if !selected_range.is_empty() {
-            selection = Some(fill(
-                Bounds::from_corners(
-                    point(
-                        bounds.left() + line[0].unwrapped_layout.x_for_index(selected_range.start),
-                        bounds.top(),
-                    ),
-                    point(
-                        bounds.left() + line[0].unwrapped_layout.x_for_index(selected_range.end),
-                        bounds.bottom(),
-                    ),
-                ),
-                rgba(0x3311ff30),
-            ));
+            selection = line.range_quads(selected_range, bounds.origin, theme.selection);
         }
 
+        let brackets = input
+            .bracket_match
+            .iter()
+            .flat_map(|(a, b)| [a, b])
+            .filter(|bracket| bracket.line == self.id)
+            .flat_map(|bracket| line.range_quads(bracket.col..bracket.col + 1, bounds.origin, theme.bracket_match()))
+            .collect();
+
+        let search = input
+            .search_ranges_on(self.id)
+            .into_iter()
+            .flat_map(|(range, current)| {
+                let color = if current { theme.search_match_current() } else { theme.search_match() };
+                line.range_quads(range, bounds.origin, color)
+            })
+            .collect();
+
         let mut cursor = Some(fill(
             Bounds::new(
                 point(bounds.left() + cursor_pos.x, bounds.top() + cursor_pos.y),
                 size(px(2.), cx.line_height()),
             ),
-            gpui::blue(),
+            theme.cursor,
         ));
 
         if input.focused_line != self.id {
             cursor = None;
             if !fully_selected {
-                selection = None;
+                selection.clear();
             }
         }
 
         PrepaintState {
-            lines: Some(line),
+            line: Some(line),
             cursor,
             selection,
+            brackets,
+            search,
         }
     }
 
@@ -388,30 +523,30 @@ impl Element for TextElement {
                 ElementInputHandler::new(bounds, self.input.clone()),
             );
         }
-        if let Some(selection) = prepaint.selection.take() {
+        for found in prepaint.search.drain(..) {
+            cx.paint_quad(found)
+        }
+        for selection in prepaint.selection.drain(..) {
             cx.paint_quad(selection)
         }
-        for line in prepaint.lines.clone().unwrap() {
-            let origin = bounds.origin;
-            let mut cursor;
-            line.paint(origin, cx.line_height(), cx).unwrap();
-
-            if focus_handle.is_focused(cx) {
-                cursor = prepaint.cursor.take();
-                if let Some(ref mut cursor) = cursor {
-                    cx.paint_quad(cursor.clone());
-                }
-            }
-
-            self.lines_pixels = line.size(cx.line_height()).height;
-            // self.request_layout(id, cx);
+        for bracket in prepaint.brackets.drain(..) {
+            cx.paint_quad(bracket)
+        }
+        let line = prepaint.line.take().unwrap();
+        line.paint(bounds.origin, cx);
 
-            if self.input.read(cx).focused_line == self.id {
-                self.input.update(cx, |input, _cx| {
-                    input.last_layout = Some(line);
-                    input.last_bounds = Some(bounds);
-                });
+        if focus_handle.is_focused(cx) {
+            if let Some(cursor) = prepaint.cursor.take() {
+                cx.paint_quad(cursor);
             }
         }
+
+        self.lines_pixels = line.height();
+        // self.request_layout(id, cx);
+
+        let buffer_line = self.id;
+        self.input.update(cx, |input, _cx| {
+            input.line_layouts.insert(buffer_line, (bounds, line));
+        });
     }
 }
@@ -0,0 +1,159 @@
+use gpui::{actions, ViewContext};
+
+use super::{edit::BufferPoint, text::TextInput};
+
+actions!(undo, [Undo, Redo]);
+
+const MAX_UNDO: usize = 1000; // transactions kept, the oldest go first
+
+/** `old_text` at `start` was replaced by `new_text`, both can hold \n */
+#[derive(Clone, Debug)]
+struct UndoEdit {
+    start: BufferPoint,
+    old_text: String,
+    new_text: String,
+}
+
+/** where `text` ends when it is put at `start` */
+fn end_of(start: BufferPoint, text: &str) -> BufferPoint {
+    match text.rsplit_once('\n') {
+        Some((before, last)) => BufferPoint::new(start.line + before.matches('\n').count() + 1, last.len()),
+        None => BufferPoint::new(start.line, start.col + text.len()),
+    }
+}
+
+/** what one undo takes back, the edits in the order they were made */
+#[derive(Clone, Debug)]
+struct Transaction {
+    edits: Vec<UndoEdit>,
+    cursor: BufferPoint, // before the first edit, undo puts it back there
+}
+
+impl Transaction {
+    /** plain typing on one line, consecutive ones undo together */
+    fn is_typing(&self) -> bool {
+        matches!(self.edits.as_slice(), [edit] if edit.old_text.is_empty() && !edit.new_text.contains('\n'))
+    }
+}
+
+/**
+ * every begin_edit/finish_edit pair is an entry, or part of the transaction a caller opened
+ * around several of them. load and reload start over
+ */
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Transaction>,
+    redo: Vec<Transaction>,
+    open: Option<Transaction>, // between begin_transaction and end_transaction
+    depth: usize, // nested transactions fold into the outermost one
+    begun: Option<(String, BufferPoint)>, // replaced text and cursor, from begin_edit
+    replaying: bool, // undo and redo don't record their own edits
+}
+
+impl History {
+    fn push(&mut self, transaction: Transaction) {
+        if transaction.edits.is_empty() {
+            return;
+        }
+        self.redo.clear();
+        if let Some(last) = self.undo.last_mut() {
+            let (previous, next) = (&last.edits[0], &transaction.edits[0]);
+            // a word typed a char at a time is one step, a jump elsewhere starts the next
+            if last.is_typing() && transaction.is_typing() && end_of(previous.start, &previous.new_text) == next.start {
+                last.edits[0].new_text.push_str(&next.new_text);
+                return;
+            }
+        }
+        self.undo.push(transaction);
+        if self.undo.len() > MAX_UNDO {
+            self.undo.remove(0);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = History::default();
+    }
+}
+
+impl TextInput {
+    /** edits until the matching end_transaction undo as one step, calls can nest */
+    pub fn begin_transaction(&mut self) {
+        self.history.depth += 1;
+        if self.history.open.is_none() {
+            self.history.open = Some(Transaction {
+                edits: vec![],
+                cursor: self.cursor_point(),
+            });
+        }
+    }
+
+    pub fn end_transaction(&mut self) {
+        self.history.depth = self.history.depth.saturating_sub(1);
+        if self.history.depth > 0 {
+            return;
+        }
+        if let Some(transaction) = self.history.open.take() {
+            self.history.push(transaction);
+        }
+    }
+
+    /** from begin_edit, the text about to be replaced */
+    pub fn record_old_text(&mut self, old_text: String) {
+        if !self.history.replaying {
+            self.history.begun = Some((old_text, self.cursor_point()));
+        }
+    }
+
+    /** from finish_edit, `new_text` now sits at `start` */
+    pub fn record_edit(&mut self, start: BufferPoint, new_text: String) {
+        let Some((old_text, cursor)) = self.history.begun.take() else {
+            return;
+        };
+        let edit = UndoEdit { start, old_text, new_text };
+        match self.history.open.as_mut() {
+            Some(transaction) => transaction.edits.push(edit),
+            None => self.history.push(Transaction { edits: vec![edit], cursor }),
+        }
+    }
+
+    pub fn undo(&mut self, _: &Undo, cx: &mut ViewContext<Self>) {
+        if self.refuse_edit(cx) {
+            return;
+        }
+        let Some(transaction) = self.history.undo.pop() else {
+            self.status_message = Some("already at oldest change".into());
+            cx.notify();
+            return;
+        };
+        self.history.replaying = true;
+        for edit in transaction.edits.iter().rev() {
+            self.replace_range(edit.start, end_of(edit.start, &edit.new_text), &edit.old_text);
+        }
+        self.history.replaying = false;
+        self.dirty = true;
+        self.jump_to_point(self.clamp_point(transaction.cursor), cx);
+        self.history.redo.push(transaction);
+        cx.notify();
+    }
+
+    pub fn redo(&mut self, _: &Redo, cx: &mut ViewContext<Self>) {
+        if self.refuse_edit(cx) {
+            return;
+        }
+        let Some(transaction) = self.history.redo.pop() else {
+            self.status_message = Some("already at newest change".into());
+            cx.notify();
+            return;
+        };
+        self.history.replaying = true;
+        let mut end = transaction.cursor;
+        for edit in &transaction.edits {
+            end = self.replace_range(edit.start, end_of(edit.start, &edit.old_text), &edit.new_text);
+        }
+        self.history.replaying = false;
+        self.dirty = true;
+        self.jump_to_point(self.clamp_point(end), cx);
+        self.history.undo.push(transaction);
+        cx.notify();
+    }
+}
@@ -0,0 +1,218 @@
+use std::ops::Range;
+
+use gpui::{actions, ViewContext, ViewInputHandler};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::settings::settings::{settings, WordSettings};
+
+use super::{
+    text::TextInput,
+    text_input::{Backspace, Delete},
+};
+
+actions!(
+    word,
+    [
+        WordLeft,
+        WordRight,
+        SelectWordLeft,
+        SelectWordRight,
+        DeleteWordLeft,
+        DeleteWordRight,
+    ]
+);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Space,
+    Word,
+    Punctuation,
+}
+
+fn class_of(segment: &str, words: &WordSettings) -> Class {
+    match segment.chars().next() {
+        Some(c) if c.is_whitespace() => Class::Space,
+        Some('_') if !words.underscore_is_word => Class::Punctuation,
+        Some(c) if c.is_alphanumeric() || c == '_' => Class::Word,
+        _ => Class::Punctuation,
+    }
+}
+
+/** where a word splits into subwords, `fooBar` | `HTTPServer` -> `HTTP` `Server` | `foo_bar` -> `foo_` `bar` */
+fn subword_starts(word: &str) -> Vec<usize> {
+    let chars: Vec<(usize, char)> = word.char_indices().collect();
+    let mut starts = vec![];
+    for i in 1..chars.len() {
+        let (ix, c) = chars[i];
+        let prev = chars[i - 1].1;
+        let next = chars.get(i + 1).map(|(_, c)| *c);
+        let hump = c.is_uppercase() && (prev.is_lowercase() || prev.is_numeric());
+        let acronym_end = c.is_uppercase() && prev.is_uppercase() && next.is_some_and(|n| n.is_lowercase());
+        let after_underscore = prev == '_' && c != '_';
+        if hump || acronym_end || after_underscore {
+            starts.push(ix);
+        }
+    }
+    starts
+}
+
+/**
+ * the words of a line as byte ranges, unicode word bounds first, then the settings for `_`,
+ * punctuation and subwords. whitespace is never a word
+ */
+pub fn word_ranges(line: &str, words: &WordSettings) -> Vec<Range<usize>> {
+    let mut ranges: Vec<(Range<usize>, Class)> = vec![];
+    for (start, segment) in line.split_word_bound_indices() {
+        let class = class_of(segment, words);
+        let range = start..start + segment.len();
+        match (class, ranges.last_mut()) {
+            // `->` or `::` are one stop, not one per char
+            (Class::Punctuation, Some((last, Class::Punctuation))) if last.end == start => last.end = range.end,
+            (Class::Word, _) if !words.underscore_is_word => {
+                // unicode joins foo_bar, split it back up
+                let mut part_start = range.start;
+                for (ix, c) in segment.char_indices() {
+                    let ix = start + ix;
+                    if c == '_' {
+                        if part_start < ix {
+                            ranges.push((part_start..ix, Class::Word));
+                        }
+                        match ranges.last_mut() {
+                            Some((last, Class::Punctuation)) if last.end == ix => last.end = ix + 1,
+                            _ => ranges.push((ix..ix + 1, Class::Punctuation)),
+                        }
+                        part_start = ix + 1;
+                    }
+                }
+                if part_start < range.end {
+                    ranges.push((part_start..range.end, Class::Word));
+                }
+            }
+            _ => ranges.push((range, class)),
+        }
+    }
+
+    let mut result = vec![];
+    for (range, class) in ranges {
+        match class {
+            Class::Space => {}
+            Class::Punctuation if !words.punctuation_stops => {}
+            Class::Word if words.subwords => {
+                let mut start = range.start;
+                for split in subword_starts(&line[range.clone()]) {
+                    result.push(start..range.start + split);
+                    start = range.start + split;
+                }
+                result.push(start..range.end);
+            }
+            _ => result.push(range),
+        }
+    }
+    result
+}
+
+/** end of the word the offset is in, or of the next one */
+pub fn next_word_end(line: &str, offset: usize, words: &WordSettings) -> Option<usize> {
+    word_ranges(line, words).into_iter().find(|range| range.end > offset).map(|range| range.end)
+}
+
+/** start of the word the offset is in, or of the previous one */
+pub fn previous_word_start(line: &str, offset: usize, words: &WordSettings) -> Option<usize> {
+    word_ranges(line, words).into_iter().rev().find(|range| range.start < offset).map(|range| range.start)
+}
+
+// ctrl-left/right and friends, running off either end of a line carries on into the next one
+impl TextInput {
+    fn word_left_offset(&self, cx: &ViewContext<Self>) -> Option<usize> {
+        let line = &self.content[self.focused_line];
+        previous_word_start(line, self.cursor_offset(), &settings(cx).words)
+            .or((self.cursor_offset() > 0).then_some(0))
+    }
+
+    fn word_right_offset(&self, cx: &ViewContext<Self>) -> Option<usize> {
+        let line = &self.content[self.focused_line];
+        next_word_end(line, self.cursor_offset(), &settings(cx).words)
+            .or((self.cursor_offset() < line.len()).then_some(line.len()))
+    }
+
+    pub fn word_left(&mut self, _: &WordLeft, cx: &mut ViewContext<Self>) {
+        self.selected_lines = 0..0;
+        match self.word_left_offset(cx) {
+            Some(offset) => self.move_to(offset, cx),
+            None if self.focused_line > 0 => {
+                self.focused_line -= 1;
+                self.move_to(self.content[self.focused_line].len(), cx);
+            }
+            None => {}
+        }
+    }
+
+    pub fn word_right(&mut self, _: &WordRight, cx: &mut ViewContext<Self>) {
+        self.selected_lines = 0..0;
+        match self.word_right_offset(cx) {
+            Some(offset) => self.move_to(offset, cx),
+            None if self.focused_line + 1 < self.lines => {
+                self.focused_line += 1;
+                self.move_to(0, cx);
+            }
+            None => {}
+        }
+    }
+
+    pub fn select_word_left(&mut self, _: &SelectWordLeft, cx: &mut ViewContext<Self>) {
+        match self.word_left_offset(cx) {
+            Some(offset) => self.select_to(offset, cx),
+            None if self.focused_line > 0 => {
+                let line = self.focused_line - 1;
+                self.extend_selection_to(line, self.content[line].len());
+            }
+            None => {}
+        }
+        self.is_selecting = true;
+    }
+
+    pub fn select_word_right(&mut self, _: &SelectWordRight, cx: &mut ViewContext<Self>) {
+        match self.word_right_offset(cx) {
+            Some(offset) => self.select_to(offset, cx),
+            None if self.focused_line + 1 < self.lines => self.extend_selection_to(self.focused_line + 1, 0),
+            None => {}
+        }
+        self.is_selecting = true;
+    }
+
+    /** ctrl-backspace, at the start of a line it joins with the one above like backspace */
+    pub fn delete_word_left(&mut self, _: &DeleteWordLeft, cx: &mut ViewContext<Self>) {
+        if !self.selected_range.is_empty() || self.is_selecting {
+            self.backspace(&Backspace, cx);
+            return;
+        }
+        match self.word_left_offset(cx) {
+            Some(offset) => {
+                self.select_to(offset, cx);
+                self.replace_text_in_range(None, "", cx);
+            }
+            None => self.backspace(&Backspace, cx),
+        }
+    }
+
+    /** ctrl-delete, at the end of a line it pulls the next one up */
+    pub fn delete_word_right(&mut self, _: &DeleteWordRight, cx: &mut ViewContext<Self>) {
+        if !self.selected_range.is_empty() || self.is_selecting {
+            self.delete(&Delete, cx);
+            return;
+        }
+        match self.word_right_offset(cx) {
+            Some(offset) => {
+                self.select_to(offset, cx);
+                self.replace_text_in_range(None, "", cx);
+            }
+            None if self.focused_line + 1 < self.lines => {
+                // same as backspace at the start of the next line
+                self.focused_line += 1;
+                self.move_to(0, cx);
+                self.backspace(&Backspace, cx);
+            }
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use std::ops::Range;
+
+use gpui::{
+    actions, fill, point, px, size, Bounds, Hsla, PaintQuad, Pixels, Point, ShapedLine, SharedString,
+    TextRun, ViewContext, WindowContext,
+};
+
+use crate::settings::settings::{settings, SoftWrap};
+
+use super::text::TextInput;
+
+actions!(wrap, [ToggleSoftWrap]);
+
+/** one screen row of a buffer line */
+#[derive(Clone)]
+pub struct DisplayRow {
+    pub range: Range<usize>, // bytes of the buffer line
+    pub indent: Pixels, // continuation rows line up with the line's own indent
+    pub shaped: ShapedLine,
+}
+
+/** a buffer line shaped into display rows, one row when soft wrap is off */
+#[derive(Clone)]
+pub struct DisplayLine {
+    pub rows: Vec<DisplayRow>,
+    pub line_height: Pixels,
+}
+
+impl DisplayLine {
+    pub fn shape(
+        text: SharedString,
+        runs: &[TextRun],
+        font_size: Pixels,
+        wrap_width: Option<Pixels>,
+        cx: &WindowContext,
+    ) -> DisplayLine {
+        let line_height = cx.line_height();
+        let text_system = cx.text_system();
+
+        let mut boundaries = vec![];
+        if let (Some(wrap_width), Some(run)) = (wrap_width, runs.first()) {
+            let mut wrapper = text_system.line_wrapper(run.font.clone(), font_size);
+            boundaries.extend(wrapper.wrap_line(&text, wrap_width));
+        }
+
+        let mut rows = Vec::with_capacity(boundaries.len() + 1);
+        let mut start = 0;
+        let mut indent = px(0.);
+        for end in boundaries.iter().map(|boundary| boundary.ix).chain([text.len()]) {
+            let shaped = text_system
+                .shape_line(text[start..end].to_string().into(), font_size, &slice_runs(runs, start..end))
+                .unwrap_or_default();
+            rows.push(DisplayRow {
+                range: start..end,
+                indent,
+                shaped,
+            });
+            start = end;
+        }
+        // leading whitespace is the same for every boundary, measure it on the first row
+        if let (Some(boundary), Some(first)) = (boundaries.first(), rows.first()) {
+            let indent = first.shaped.x_for_index(boundary.next_indent as usize);
+            for row in rows.iter_mut().skip(1) {
+                row.indent = indent;
+            }
+        }
+
+        DisplayLine { rows, line_height }
+    }
+
+    pub fn height(&self) -> Pixels {
+        self.line_height * self.rows.len() as f32
+    }
+
+    /** an index on a wrap boundary belongs to the row it starts */
+    pub fn row_for_index(&self, index: usize) -> usize {
+        self.rows.iter().rposition(|row| row.range.start <= index).unwrap_or(0)
+    }
+
+    /** relative to the top left of the line */
+    pub fn position_for_index(&self, index: usize) -> Point<Pixels> {
+        let row_ix = self.row_for_index(index);
+        let row = &self.rows[row_ix];
+        let x = row.indent + row.shaped.x_for_index(index.saturating_sub(row.range.start));
+        point(x, self.line_height * row_ix as f32)
+    }
+
+    pub fn index_for_row_x(&self, row_ix: usize, x: Pixels) -> usize {
+        let row = &self.rows[row_ix.min(self.rows.len() - 1)];
+        row.range.start + row.shaped.closest_index_for_x(x - row.indent)
+    }
+
+    /** relative to the top left of the line, rows above/below clamp to the first/last */
+    pub fn index_for_position(&self, position: Point<Pixels>) -> usize {
+        let row_ix = (position.y / self.line_height).max(0.) as usize;
+        self.index_for_row_x(row_ix, position.x)
+    }
+
+    /** a quad per row the range touches */
+    pub fn range_quads(&self, range: Range<usize>, origin: Point<Pixels>, color: Hsla) -> Vec<PaintQuad> {
+        let mut quads = vec![];
+        for (row_ix, row) in self.rows.iter().enumerate() {
+            let start = range.start.max(row.range.start);
+            let end = range.end.min(row.range.end);
+            if start >= end {
+                continue;
+            }
+            let top = origin.y + self.line_height * row_ix as f32;
+            let left = origin.x + row.indent + row.shaped.x_for_index(start - row.range.start);
+            let right = origin.x + row.indent + row.shaped.x_for_index(end - row.range.start);
+            quads.push(fill(Bounds::new(point(left, top), size(right - left, self.line_height)), color));
+        }
+        quads
+    }
+
+    pub fn paint(&self, origin: Point<Pixels>, cx: &mut WindowContext) {
+        for (row_ix, row) in self.rows.iter().enumerate() {
+            let origin = point(origin.x + row.indent, origin.y + self.line_height * row_ix as f32);
+            let _ = row.shaped.paint(origin, self.line_height, cx);
+        }
+    }
+}
+
+/** the part of `runs` covering `range`, with lengths cut to fit */
+fn slice_runs(runs: &[TextRun], range: Range<usize>) -> Vec<TextRun> {
+    let mut sliced = vec![];
+    let mut start = 0;
+    for run in runs {
+        let end = start + run.len;
+        let overlap = range.start.max(start)..range.end.min(end);
+        if overlap.start < overlap.end {
+            sliced.push(TextRun {
+                len: overlap.end - overlap.start,
+                ..run.clone()
+            });
+        }
+        start = end;
+    }
+    if sliced.is_empty() {
+        // shaping wants a run even for an empty row
+        if let Some(run) = runs.first() {
+            sliced.push(TextRun { len: 0, ..run.clone() });
+        }
+    }
+    sliced
+}
+
+impl TextInput {
+    /** the buffer's own setting wins over the filetype and global ones */
+    pub fn soft_wrap(&self, cx: &WindowContext) -> SoftWrap {
+        self.soft_wrap.unwrap_or_else(|| settings(cx).soft_wrap_for(&self.open_file))
+    }
+
+    /** wraps at the configured width, or back to horizontal scrolling */
+    pub fn set_soft_wrap(&mut self, on: bool, cx: &mut ViewContext<Self>) {
+        let configured = settings(cx).soft_wrap_for(&self.open_file);
+        self.soft_wrap = Some(match (on, configured) {
+            (false, _) => SoftWrap::Off,
+            (true, SoftWrap::Off) => SoftWrap::Viewport,
+            (true, configured) => configured,
+        });
+        self.last_cursor_scroll = px(0.);
+        cx.notify();
+    }
+
+    pub fn toggle_soft_wrap(&mut self, _: &ToggleSoftWrap, cx: &mut ViewContext<Self>) {
+        let on = self.soft_wrap(cx) == SoftWrap::Off;
+        self.set_soft_wrap(on, cx);
+    }
+
+    /** `text_width` is what is left of the viewport after the gutter */
+    pub fn wrap_width_for(&self, text_width: Pixels, char_width: Pixels, cx: &WindowContext) -> Option<Pixels> {
+        match self.soft_wrap(cx) {
+            SoftWrap::Off => None,
+            SoftWrap::Viewport => Some(text_width),
+            SoftWrap::Column => Some((char_width * settings(cx).wrap_column as f32).min(text_width)),
+        }
+    }
+
+    /** plain shaping of a buffer line, widths don't depend on highlight colors */
+    pub fn layout_line(&self, line: usize, cx: &WindowContext) -> DisplayLine {
+        let settings = settings(cx);
+        let content = self.content[line].clone();
+        let run = TextRun {
+            len: content.len(),
+            font: settings.font(),
+            color: cx.text_style().color,
+            background_color: None,
+            underline: None,
+            strikethrough: None,
+        };
+        self.layout_cache.layout(content, &[run], settings.metrics().font_size, self.wrap_width, cx)
+    }
+
+    pub fn display_rows(&self, line: usize, cx: &WindowContext) -> usize {
+        match self.wrap_width {
+            Some(_) => self.layout_line(line, cx).rows.len(),
+            None => 1,
+        }
+    }
+
+    /**
+     * gj/gk, moves one display row keeping the x position, returns false when soft wrap is
+     * off so up/down fall back to whole lines
+     */
+    pub fn move_display_row(&mut self, down: bool, cx: &mut ViewContext<Self>) -> bool {
+        let Some((line, index)) = self.display_row_target(down, cx) else {
+            return false;
+        };
+        self.focused_line = line;
+        self.selected_lines = 0..0;
+        self.selection_reversed = false;
+        self.marked_range = None;
+        self.move_to(index, cx);
+        true
+    }
+
+    /** line and offset one display row from the cursor, None when soft wrap is off */
+    pub fn display_row_target(&mut self, down: bool, cx: &mut ViewContext<Self>) -> Option<(usize, usize)> {
+        self.wrap_width?;
+        let layout = self.layout_line(self.focused_line, cx);
+        let cursor = self.cursor_offset();
+        let row = layout.row_for_index(cursor);
+        let x = layout.position_for_index(cursor).x;
+
+        let target = if down && row + 1 < layout.rows.len() {
+            (self.focused_line, layout.index_for_row_x(row + 1, x))
+        } else if !down && row > 0 {
+            (self.focused_line, layout.index_for_row_x(row - 1, x))
+        } else if down && self.focused_line + 1 < self.lines {
+            let line = self.focused_line + 1;
+            (line, self.layout_line(line, cx).index_for_row_x(0, x))
+        } else if !down && self.focused_line > 0 {
+            let line = self.focused_line - 1;
+            let layout = self.layout_line(line, cx);
+            (line, layout.index_for_row_x(layout.rows.len() - 1, x))
+        } else {
+            (self.focused_line, cursor)
+        };
+        Some(target)
+    }
+}
@@ -0,0 +1,246 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use futures::StreamExt;
+use gpui::{rgba, AppContext, Global, Hsla};
+use serde::{Deserialize, Deserializer};
+
+use crate::{files::watcher::watch_dir, settings::settings::{config_dir, settings}};
+
+const BUNDLED_THEMES: [&str; 3] = [
+    include_str!("../../assets/themes/light.json"),
+    include_str!("../../assets/themes/dark.json"),
+    include_str!("../../assets/themes/solarized-light.json"),
+];
+pub const DEFAULT_THEME: &str = "light";
+
+/** every color the editor paints with, loaded from a theme json file */
+#[derive(Clone, Debug, Deserialize)]
+pub struct Theme {
+    pub name: String,
+    #[serde(deserialize_with = "color")]
+    pub background: Hsla,
+    #[serde(deserialize_with = "color")]
+    pub foreground: Hsla,
+    #[serde(deserialize_with = "color")]
+    pub selection: Hsla,
+    #[serde(deserialize_with = "color")]
+    pub cursor: Hsla,
+    #[serde(deserialize_with = "color")]
+    pub gutter_background: Hsla,
+    #[serde(deserialize_with = "color")]
+    pub gutter_foreground: Hsla,
+    #[serde(deserialize_with = "color")]
+    pub gutter_active_foreground: Hsla,
+    #[serde(deserialize_with = "color")]
+    pub error: Hsla,
+    #[serde(deserialize_with = "color")]
+    pub warning: Hsla,
+    #[serde(deserialize_with = "color")]
+    pub info: Hsla,
+    #[serde(deserialize_with = "color")]
+    pub hint: Hsla,
+    #[serde(deserialize_with = "color")]
+    pub ui_background: Hsla,
+    #[serde(deserialize_with = "color")]
+    pub status_bar_background: Hsla,
+    #[serde(deserialize_with = "color")]
+    pub status_bar_foreground: Hsla,
+    // optional so theme files from before it still load, see the methods below
+    #[serde(default, deserialize_with = "optional_color")]
+    pub bracket_match: Option<Hsla>,
+    #[serde(default, deserialize_with = "optional_color")]
+    pub search_match: Option<Hsla>,
+    #[serde(default, deserialize_with = "optional_color")]
+    pub search_match_current: Option<Hsla>,
+    #[serde(default, deserialize_with = "syntax_colors")]
+    pub syntax: HashMap<String, Hsla>, // tree-sitter capture name -> color
+}
+
+impl Theme {
+    /** behind the bracket under the cursor and its partner */
+    pub fn bracket_match(&self) -> Hsla {
+        self.bracket_match.unwrap_or(self.selection)
+    }
+
+    /** behind every search match */
+    pub fn search_match(&self) -> Hsla {
+        self.search_match.unwrap_or(self.warning.opacity(0.25))
+    }
+
+    /** behind the match n of m counts from */
+    pub fn search_match_current(&self) -> Hsla {
+        self.search_match_current.unwrap_or(self.warning.opacity(0.6))
+    }
+
+    /** exact scope first, then its parents (function.method -> function) */
+    pub fn syntax_color(&self, scope: &str) -> Option<Hsla> {
+        let mut scope = scope;
+        loop {
+            if let Some(color) = self.syntax.get(scope) {
+                return Some(*color);
+            }
+            scope = scope.rsplit_once('.')?.0;
+        }
+    }
+}
+
+/** "#rrggbb" or "#rrggbbaa" */
+pub fn parse_color(hex: &str) -> Option<Hsla> {
+    let hex = hex.strip_prefix('#')?;
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    match hex.len() {
+        6 => Some(rgba((value << 8) | 0xff).into()),
+        8 => Some(rgba(value).into()),
+        _ => None,
+    }
+}
+
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Hsla, D::Error> {
+    let hex = String::deserialize(deserializer)?;
+    parse_color(&hex).ok_or_else(|| serde::de::Error::custom(format!("invalid color {}", hex)))
+}
+
+fn optional_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Hsla>, D::Error> {
+    color(deserializer).map(Some)
+}
+
+fn syntax_colors<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<String, Hsla>, D::Error> {
+    let colors = HashMap::<String, String>::deserialize(deserializer)?;
+    colors
+        .into_iter()
+        .map(|(scope, hex)| {
+            let color = parse_color(&hex)
+                .ok_or_else(|| serde::de::Error::custom(format!("invalid color {} for {}", hex, scope)))?;
+            Ok((scope, color))
+        })
+        .collect()
+}
+
+/**
+ * all known themes and which one is showing, lives in the app as a global. themes are
+ * shared so views can hold the active one for a frame without copying it
+ */
+pub struct ThemeRegistry {
+    bundled: HashMap<String, Arc<Theme>>,
+    themes: HashMap<String, Arc<Theme>>, // bundled plus the user dir
+    active: String,
+    user_dir: Option<PathBuf>,
+}
+
+impl Global for ThemeRegistry {}
+
+impl ThemeRegistry {
+    fn load() -> Self {
+        let mut registry = ThemeRegistry {
+            bundled: HashMap::new(),
+            themes: HashMap::new(),
+            active: DEFAULT_THEME.to_string(),
+            user_dir: user_theme_dir(),
+        };
+        for source in BUNDLED_THEMES {
+            let theme: Theme = serde_json::from_str(source).expect("bundled theme is invalid");
+            registry.bundled.insert(theme.name.clone(), Arc::new(theme));
+        }
+        registry.load_user_themes();
+        registry
+    }
+
+    /**
+     * user themes override bundled ones with the same name, broken files get skipped.
+     * starts over from the bundled ones so deleted or renamed files drop out
+     */
+    fn load_user_themes(&mut self) {
+        self.themes = self.bundled.clone();
+        let Some(dir) = self.user_dir.as_ref() else {
+            return;
+        };
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|extension| extension == "json") {
+                match load_theme_file(&path) {
+                    Ok(theme) => {
+                        self.themes.insert(theme.name.clone(), Arc::new(theme));
+                    }
+                    Err(err) => eprintln!("skipping theme {}: {}", path.display(), err),
+                }
+            }
+        }
+    }
+
+    pub fn active(&self) -> &Arc<Theme> {
+        self.themes
+            .get(&self.active)
+            .or_else(|| self.themes.get(DEFAULT_THEME))
+            .expect("default theme is bundled")
+    }
+
+    pub fn set_active(&mut self, name: &str) -> Result<(), String> {
+        if !self.themes.contains_key(name) {
+            let mut names: Vec<&String> = self.themes.keys().collect();
+            names.sort();
+            return Err(format!("no theme {}, have {:?}", name, names));
+        }
+        self.active = name.to_string();
+        Ok(())
+    }
+}
+
+fn load_theme_file(path: &Path) -> Result<Theme, String> {
+    let source = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    serde_json::from_str(&source).map_err(|err| err.to_string())
+}
+
+fn user_theme_dir() -> Option<PathBuf> {
+    Some(config_dir()?.join("themes"))
+}
+
+pub fn theme(cx: &AppContext) -> Arc<Theme> {
+    cx.global::<ThemeRegistry>().active().clone()
+}
+
+/** loads the themes and reloads user themes whenever a file in the theme dir is saved */
+pub fn init(cx: &mut AppContext) {
+    let mut registry = ThemeRegistry::load();
+    if let Some(name) = settings(cx).theme.clone() {
+        if let Err(err) = registry.set_active(&name) {
+            eprintln!("{}", err);
+        }
+    }
+    let user_dir = registry.user_dir.clone();
+    cx.set_global(registry);
+
+    let Some(dir) = user_dir.filter(|dir| dir.is_dir()) else {
+        return;
+    };
+    let (watcher, mut changes) = match watch_dir(&dir, |path| {
+        path.extension().is_some_and(|extension| extension == "json")
+    }) {
+        Ok(watch) => watch,
+        Err(err) => {
+            eprintln!("not watching {}: {}", dir.display(), err);
+            return;
+        }
+    };
+    cx.spawn(|cx| async move {
+        let _watcher = watcher;
+        while changes.next().await.is_some() {
+            let updated = cx.update(|cx| {
+                cx.update_global::<ThemeRegistry, _>(|registry, _| registry.load_user_themes());
+                // views paint straight from the registry so a redraw is enough
+                cx.refresh();
+            });
+            if updated.is_err() {
+                break;
+            }
+        }
+    })
+    .detach();
+}